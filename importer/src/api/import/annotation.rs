@@ -1,46 +1,121 @@
-use std::fmt::{self, Display};
+use std::{fmt::{self, Display}, path::PathBuf};
 
 use chrono::{DateTime, FixedOffset};
-use serde::{de::{self, Visitor}, Deserialize};
+use serde::{de::{self, Visitor}, Deserialize, Serialize};
 
 #[allow(unused)]
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Annotation {
 	pub key: String,
 	pub kind: AnnotationType,
-	pub page: u32,
+	/// The key of the PDF attachment the annotation was made in, filled in from the attachment it is listed under.
+	#[serde(skip_deserializing)]
+	pub attachment: String,
+	pub position: AnnotationPosition,
 	pub text: Option<String>,
 	pub comment: Option<String>,
 	pub colour: Colour,
 	pub date_added: DateTime<FixedOffset>,
 	pub date_modified: DateTime<FixedOffset>,
-	pub tags: Box<[Tag]>
+	pub tags: Box<[Tag]>,
+	/// The rendered region of an image annotation, if Zotero has one available.
+	#[serde(skip_serializing)]
+	pub image: Option<AnnotationImage>
 }
 
+impl Annotation {
+	/// Returns a link that opens the annotation in Zotero's PDF reader.
+	pub fn open_link(&self) -> String {
+		format!("zotero://open-pdf/library/items/{}?page={}&annotation={}", self.attachment, self.position.page_index + 1, self.key)
+	}
+}
+
+/// Where an annotation is located within its attachment.
 #[allow(unused)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnnotationPosition {
+	/// The zero-based index of the page within the PDF.
+	pub page_index: u32,
+	/// The label the PDF gives the page, which need not be numeric (eg. roman numerals in front matter).
+	pub page_label: String,
+	/// The areas covered by the annotation, as `[x1, y1, x2, y2]` in PDF coordinates.
+	#[serde(default)]
+	pub rects: Box<[[f64; 4]]>
+}
+
+impl AnnotationPosition {
+	/// The height of the top edge of the annotation, as PDF coordinates increase upwards.
+	pub fn top(&self) -> f64 {
+		self.rects.iter().map(|[_, y1, _, y2]| y1.max(*y2)).fold(0.0, f64::max)
+	}
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum AnnotationType {
 	Highlight,
+	Underline,
+	/// A sticky note, placed at a point on the page.
+	Note,
+	/// A free-text box drawn onto the page.
+	Text,
+	/// A rectangular image region.
+	Image,
+	/// A freehand drawing.
+	Ink,
 	#[serde(untagged)]
 	Unknown
 }
 
+impl AnnotationType {
+	pub const ALL: [AnnotationType; 7] = [
+		AnnotationType::Highlight,
+		AnnotationType::Underline,
+		AnnotationType::Note,
+		AnnotationType::Text,
+		AnnotationType::Image,
+		AnnotationType::Ink,
+		AnnotationType::Unknown
+	];
+}
+
+/// The image data of an image annotation, either embedded directly or read from Zotero's image cache.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnotationImage {
+	/// A path to a PNG file.
+	Path(PathBuf),
+	/// Base64 encoded PNG data.
+	Data(String)
+}
+
 #[allow(unused)]
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Tag {
 	pub name: String
 }
 
 #[allow(unused)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Colour {
 	pub r: u8,
 	pub g: u8,
 	pub b: u8
 }
 
+impl Colour {
+	/// The euclidean distance between two colours in RGB space.
+	pub fn distance(&self, other: &Colour) -> f64 {
+		let channel = |a: u8, b: u8| (f64::from(a) - f64::from(b)).powi(2);
+
+		(channel(self.r, other.r) + channel(self.g, other.g) + channel(self.b, other.b)).sqrt()
+	}
+}
+
 impl Display for Colour {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		let Self { r, g, b } = self;
@@ -48,6 +123,12 @@ impl Display for Colour {
 	}
 }
 
+impl Serialize for Colour {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
 impl<'de> Deserialize<'de> for Colour {
 	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
 		deserializer.deserialize_str(ColourVisitor)
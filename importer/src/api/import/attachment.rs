@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+use super::annotation::Annotation;
+
+/// A PDF attachment of a document, along with the annotations made in it.
+#[allow(unused)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AttachmentImport {
+	pub key: String,
+	pub title: String,
+	pub path: String,
+	#[serde(skip_serializing)]
+	pub annotations: Box<[Annotation]>
+}
\ No newline at end of file
@@ -1,11 +1,68 @@
-use serde::Deserialize;
+use std::mem;
 
-use super::{annotation::Annotation, source::SourceImport};
+use serde::{de, Deserialize};
+
+use super::{annotation::Annotation, attachment::AttachmentImport, note::NoteImport, source::SourceImport};
 
 #[allow(unused)]
 #[derive(Debug, Clone, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Export {
 	pub source: SourceImport,
-	pub annotations: Box<[Annotation]>
+	pub attachments: Box<[AttachmentImport]>,
+	#[serde(default)]
+	pub notes: Box<[NoteImport]>
+}
+
+impl Export {
+	/// Separates the annotations of every attachment from the attachments themselves, recording on each annotation which attachment it came from.
+	pub fn into_parts(self) -> (SourceImport, Vec<AttachmentImport>, Vec<NoteImport>, Vec<Annotation>) {
+		let mut attachments = self.attachments.into_vec();
+
+		let annotations = attachments.iter_mut().flat_map(|attachment| {
+			let key = attachment.key.clone();
+
+			mem::take(&mut attachment.annotations).into_vec().into_iter().map(move |annotation| Annotation { attachment: key.clone(), ..annotation })
+		}).collect();
+
+		(self.source, attachments, self.notes.into_vec(), annotations)
+	}
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExportBatch {
+	pub documents: Box<[Export]>
+}
+
+/// The contents of an export file, holding either a single document or a batch of them.
+#[derive(Debug, Clone)]
+pub enum ExportFile {
+	Single(Box<Export>),
+	Batch(ExportBatch)
+}
+
+impl ExportFile {
+	pub fn into_documents(self) -> Vec<Export> {
+		match self {
+			ExportFile::Single(export) => vec![*export],
+			ExportFile::Batch(batch) => batch.documents.into_vec()
+		}
+	}
+}
+
+/// Distinguished by the presence of a `documents` field, rather than through `#[serde(untagged)]`, so that errors describe the actual problem instead of only reporting that neither shape matched.
+///
+/// The document is read through a `serde_json::Value` first, so errors do not include the path of the failing field.
+impl<'de> Deserialize<'de> for ExportFile {
+	fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let value = serde_json::Value::deserialize(deserializer)?;
+
+		let result = match value.get("documents") {
+			Some(_) => ExportBatch::deserialize(value).map(ExportFile::Batch),
+			None => Export::deserialize(value).map(|export| ExportFile::Single(Box::new(export)))
+		};
+
+		result.map_err(de::Error::custom)
+	}
 }
\ No newline at end of file
@@ -2,4 +2,6 @@ mod file;
 pub use file::*;
 
 pub mod source;
-pub mod annotation;
\ No newline at end of file
+pub mod annotation;
+pub mod attachment;
+pub mod note;
\ No newline at end of file
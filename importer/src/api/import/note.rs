@@ -0,0 +1,15 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+
+/// A child note of a document, holding Zotero rich text.
+#[allow(unused)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NoteImport {
+	pub key: String,
+	pub title: String,
+	#[serde(skip_serializing)]
+	pub content: String,
+	pub date_added: DateTime<FixedOffset>,
+	pub date_modified: DateTime<FixedOffset>
+}
\ No newline at end of file
@@ -1,13 +1,18 @@
-use std::io;
+use std::{fmt::{self, Display}, io};
 
 use chrono::format::StrftimeItems;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{api::shared::{Author, Name}, util::human_date::{self, HumanDate}};
+use crate::{api::shared::{Creator, CreatorRole, Name}, util::human_date::{self, HumanDate}};
+
+/// Written in place of the creators of documents that credit no one.
+pub const ANONYMOUS: &str = "Anonymous";
+/// Written in place of the year of undated documents.
+pub const NO_DATE: &str = "n.d.";
 
 // ! TODO: Sanity check this (eg for non emptiness) on import.
 #[allow(unused)]
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct SourceImport {
 	pub library: i32,
@@ -18,43 +23,188 @@ pub struct SourceImport {
 	pub note: Option<String>,
 	pub date: String,
 	pub url: Option<String>,
-	pub authors: Vec<Author>,
+	/// Every creator of the document, in Zotero's order.
+	pub creators: Vec<Creator>,
+	pub metadata: Metadata,
 	pub tags: Vec<Tag>,
 	pub date_added: String,
-	pub date_modified: String,
-	pub path: String
+	pub date_modified: String
+}
+
+/// Bibliographic metadata of a document, holding only the fields that are set in Zotero.
+///
+/// Fields are read through Zotero's base fields, so `publication` holds the journal, book, or website title as appropriate for the item type.
+#[allow(unused)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct Metadata {
+	/// The full title, where `SourceImport::title` prefers the short title.
+	pub title: Option<String>,
+	pub short_title: Option<String>,
+	pub publication: Option<String>,
+	pub volume: Option<String>,
+	pub issue: Option<String>,
+	pub pages: Option<String>,
+	pub edition: Option<String>,
+	pub series: Option<String>,
+	pub publisher: Option<String>,
+	pub place: Option<String>,
+	pub doi: Option<String>,
+	pub isbn: Option<String>,
+	pub issn: Option<String>,
+	pub language: Option<String>,
+	/// A citation key set through Better BibTeX or the `Citation Key:` line of the extra field.
+	pub citation_key: Option<String>
 }
 
 #[allow(unused)]
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Tag {
 	pub name: String
 }
 
+/// Who a document is attributed to, which is the first of its authors, editors, or institutional creators that it has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Attribution {
+	Authors,
+	Editors,
+	/// Creators in any role whose names are not split into first and last names.
+	Institutions,
+	/// No creators at all, written as "Anonymous".
+	Anonymous
+}
+
+/// A detail missing from a document that was replaced by a fallback.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fallback {
+	Editors,
+	Institutions,
+	Anonymous,
+	/// The date is missing, or no year could be read from it.
+	Date(String)
+}
+
+impl Display for Fallback {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Fallback::Editors => write!(f, "No authors, so it is attributed to its editors"),
+			Fallback::Institutions => write!(f, "No authors or editors, so it is attributed to its institutional creators"),
+			Fallback::Anonymous => write!(f, "No creators, so it is attributed to \"{ANONYMOUS}\""),
+			Fallback::Date(date) if date.trim().is_empty() => write!(f, "No date, so its year is written as \"{NO_DATE}\""),
+			Fallback::Date(date) => write!(f, "No year in its date '{date}', so its year is written as \"{NO_DATE}\"")
+		}
+	}
+}
+
 impl SourceImport {
-	// TODO: Handle errors.
-	pub fn parse_date(&self) -> Result<HumanDate, ()> {		
-		Ok(HumanDate::parse(&self.date).map_err(|_| ())?)
+	/// Parses the date, falling back to a lone four digit year within it (as in "2020-05-01") if it is not in the form "Month Year".
+	pub fn parse_date(&self) -> Result<HumanDate, human_date::ParseError> {
+		HumanDate::parse(&self.date).or_else(|error| {
+			let mut years = self.date.split(|c: char| !c.is_ascii_digit()).filter(|part| part.len() == 4);
+
+			match (years.next(), years.next()) {
+				(Some(year), None) => Ok(HumanDate { year: year.parse().map_err(|_| error)?, month: None }),
+				_ => Err(error)
+			}
+		})
 	}
 
-	pub fn year(&self) -> u32 {
-		self.parse_date().unwrap().year
+	pub fn year(&self) -> Option<u32> {
+		self.parse_date().ok().map(|date| date.year)
 	}
 
-	pub fn primary_author(&self) -> &Author {
-		self.authors.iter().next().unwrap()
+	/// The year, or "n.d." for undated documents.
+	pub fn year_label(&self) -> String {
+		self.year().map(|year| year.to_string()).unwrap_or_else(|| NO_DATE.to_owned())
+	}
+
+	/// The creators credited in a particular role.
+	pub fn creators_with_role(&self, role: CreatorRole) -> impl Iterator<Item = &Creator> {
+		self.creators.iter().filter(move |creator| creator.role == role)
+	}
+
+	/// The creators the document is attributed to, which are empty for anonymous documents.
+	pub fn attribution(&self) -> (Attribution, Vec<&Creator>) {
+		let authors: Vec<&Creator> = self.creators_with_role(CreatorRole::Author).collect();
+		if !authors.is_empty() { return (Attribution::Authors, authors); }
+
+		let editors: Vec<&Creator> = self.creators_with_role(CreatorRole::Editor).collect();
+		if !editors.is_empty() { return (Attribution::Editors, editors); }
+
+		let institutions: Vec<&Creator> = self.creators.iter().filter(|creator| matches!(creator.name, Name::Combined(_))).collect();
+		if !institutions.is_empty() { return (Attribution::Institutions, institutions); }
+
+		(Attribution::Anonymous, Vec::new())
+	}
+
+	/// The last name of the first creator the document is attributed to, or "Anonymous".
+	pub fn attributed_name(&self) -> String {
+		self.attribution().1.first().map(|creator| creator.name.last().trim().to_owned()).filter(|name| !name.is_empty()).unwrap_or_else(|| ANONYMOUS.to_owned())
 	}
 
 	pub fn short_name(&self) -> String {
-		match &self.primary_author().name {
-			Name::Full(full) => format!("{} {}", full.last, self.year()),
-			Name::Combined(combined) => format!("{} {}", combined, self.year()),
+		format!("{} {}", self.attributed_name(), self.year_label())
+	}
+
+	/// Lists the fallbacks used in place of the document's missing details.
+	pub fn fallbacks(&self) -> Vec<Fallback> {
+		let mut out = Vec::new();
+
+		match self.attribution().0 {
+			Attribution::Authors => (),
+			Attribution::Editors => out.push(Fallback::Editors),
+			Attribution::Institutions => out.push(Fallback::Institutions),
+			Attribution::Anonymous => out.push(Fallback::Anonymous)
 		}
+
+		if self.year().is_none() { out.push(Fallback::Date(self.date.clone())); }
+
+		out
+	}
+}
+
+#[cfg(test)]
+impl SourceImport {
+	/// A journal article with the key "KEY" and the title "Title", dated 2020, without creators or metadata, for tests to build on.
+	pub fn fixture() -> Self {
+		Self {
+			library: 1, id: 1, key: "KEY".to_owned(), kind: "journalArticle".to_owned(), title: "Title".to_owned(), note: None, date: "2020".to_owned(), url: None,
+			creators: Vec::new(), metadata: Metadata::default(), tags: Vec::new(), date_added: String::new(), date_modified: String::new()
+		}
+	}
+
+	pub fn with_key(self, key: &str) -> Self {
+		Self { key: key.to_owned(), ..self }
+	}
+
+	pub fn with_kind(self, kind: &str) -> Self {
+		Self { kind: kind.to_owned(), ..self }
+	}
+
+	pub fn with_title(self, title: &str) -> Self {
+		Self { title: title.to_owned(), ..self }
 	}
 
-	pub fn file_name(&self) -> String {
-		self.title.clone()
+	pub fn with_date(self, date: &str) -> Self {
+		Self { date: date.to_owned(), ..self }
+	}
+
+	/// Adds a creator whose name is split into first and last names.
+	pub fn with_person(mut self, role: CreatorRole, first: &str, last: &str) -> Self {
+		self.creators.push(Creator { role, name: Name::Full(crate::api::shared::FullName { first: first.to_owned(), last: last.to_owned() }) });
+		self
+	}
+
+	/// Adds a creator whose name is not split, as for organisations.
+	pub fn with_institution(mut self, role: CreatorRole, name: &str) -> Self {
+		self.creators.push(Creator { role, name: Name::Combined(name.to_owned()) });
+		self
+	}
+
+	/// Sets the metadata from its JSON form, so that only the fields a test needs are given.
+	pub fn with_metadata(self, metadata: serde_json::Value) -> Self {
+		Self { metadata: serde_json::from_value(metadata).unwrap(), ..self }
 	}
 }
 
@@ -76,4 +226,21 @@ impl From<io::Error> for ImportSourceError {
 	fn from(value: io::Error) -> Self {
 		Self::Filesystem(value)
 	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn fall_back() {
+		let edited = SourceImport::fixture().with_date("2020-05-01").with_person(CreatorRole::Editor, "Jane", "Doe");
+		assert_eq!((edited.short_name(), edited.fallbacks()), ("Doe 2020".to_owned(), vec![Fallback::Editors]));
+
+		let institutional = SourceImport::fixture().with_date("March 2021").with_institution(CreatorRole::Contributor, "WHO");
+		assert_eq!((institutional.short_name(), institutional.fallbacks()), ("WHO 2021".to_owned(), vec![Fallback::Institutions]));
+
+		let anonymous = SourceImport::fixture().with_date("");
+		assert_eq!((anonymous.short_name(), anonymous.fallbacks()), ("Anonymous n.d.".to_owned(), vec![Fallback::Anonymous, Fallback::Date(String::new())]));
+	}
 }
\ No newline at end of file
@@ -35,9 +35,14 @@ pub struct Library {
 #[serde(deny_unknown_fields)]
 pub struct Document {
 	pub id: i64,
+	/// The Zotero item key.
+	pub key: String,
 	pub title: String,
 	pub authors: Box<[Author]>,
 	pub collection_ids: Box<[i64]>,
+	/// Missing from indexes written before tags were exported, which are read as having none.
+	#[serde(default)]
+	pub tags: Box<[String]>,
 	pub date_added: String,
 	pub date_modified: String
 }
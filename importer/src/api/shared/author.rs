@@ -1,9 +1,9 @@
 use std::fmt::Display;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[allow(unused)]
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Author {
 	pub name: Name
@@ -11,7 +11,53 @@ pub struct Author {
 
 impl Display for Author {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		match &self.name {
+		write!(f, "{}", self.name)
+	}
+}
+
+/// A person or organisation credited on a document, in any role.
+#[allow(unused)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Creator {
+	pub role: CreatorRole,
+	pub name: Name
+}
+
+impl Display for Creator {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.name)
+	}
+}
+
+/// The Zotero creator types, with the less common ones folded into `Other`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CreatorRole {
+	Author,
+	Editor,
+	SeriesEditor,
+	Translator,
+	Contributor,
+	BookAuthor,
+	ReviewedAuthor,
+	#[serde(other)]
+	Other
+}
+
+impl Name {
+	/// The last name, or the whole name if it is not split (as for organisations).
+	pub fn last(&self) -> &str {
+		match self {
+			Name::Full(full) => &full.last,
+			Name::Combined(combined) => combined
+		}
+	}
+}
+
+impl Display for Name {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
 			Name::Full(full) => write!(f, "{} {}", full.first, full.last),
 			Name::Combined(combined) => write!(f, "{combined}"),
 		}
@@ -19,7 +65,7 @@ impl Display for Author {
 }
 
 #[allow(unused)]
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "format", content = "value")]
 #[serde(deny_unknown_fields)]
 pub enum Name {
@@ -28,7 +74,7 @@ pub enum Name {
 }
 
 #[allow(unused)]
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct FullName {
 	pub first: String,
@@ -0,0 +1,127 @@
+use itertools::Itertools;
+
+use crate::api::{import::source::SourceImport, shared::{CreatorRole, Name}};
+
+use super::Entry;
+
+/// BibTeX month macros, which are written without braces.
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+/// Writes a BibTeX database of every entry.
+pub fn write_bibtex(entries: &[Entry]) -> String {
+	entries.iter().map(|Entry { key, source }| {
+		let fields = fields(source).into_iter().map(|(name, value)| format!("  {name} = {value}")).join(",\n");
+
+		format!("@{}{{{key},\n{fields}\n}}\n", entry_type(&source.kind))
+	}).join("\n")
+}
+
+fn entry_type(kind: &str) -> &'static str {
+	match kind {
+		"journalArticle" | "magazineArticle" | "newspaperArticle" => "article",
+		"book" => "book",
+		"bookSection" | "encyclopediaArticle" | "dictionaryEntry" => "incollection",
+		"conferencePaper" => "inproceedings",
+		"thesis" => "phdthesis",
+		"report" => "techreport",
+		"manuscript" => "unpublished",
+		_ => "misc"
+	}
+}
+
+/// The fields of an entry, with their values already delimited.
+fn fields(source: &SourceImport) -> Vec<(&'static str, String)> {
+	let metadata = &source.metadata;
+	let entry_type = entry_type(&source.kind);
+
+	let braced = |value: &str| format!("{{{}}}", escape(value));
+	let names = |role| {
+		let names = source.creators_with_role(role).map(|creator| match &creator.name {
+			Name::Full(full) => format!("{}, {}", escape(&full.last), escape(&full.first)),
+			// * Braces keep organisations from being split into first and last names.
+			Name::Combined(combined) => format!("{{{}}}", escape(combined))
+		}).join(" and ");
+
+		(!names.is_empty()).then(|| format!("{{{names}}}"))
+	};
+
+	// * Standalone works have no containing publication.
+	let publication = match entry_type {
+		"article" => Some("journal"),
+		"incollection" | "inproceedings" => Some("booktitle"),
+		"misc" | "unpublished" => Some("howpublished"),
+		_ => None
+	};
+
+	let publisher = match entry_type {
+		"phdthesis" => "school",
+		"techreport" => "institution",
+		_ => "publisher"
+	};
+
+	let date = source.parse_date().ok();
+
+	[
+		("author", names(CreatorRole::Author)),
+		("editor", names(CreatorRole::Editor)),
+		("translator", names(CreatorRole::Translator)),
+		("title", Some(braced(metadata.title.as_deref().unwrap_or(&source.title)))),
+		(publication.unwrap_or_default(), metadata.publication.as_deref().filter(|_| publication.is_some()).map(braced)),
+		("series", metadata.series.as_deref().map(braced)),
+		("edition", metadata.edition.as_deref().map(braced)),
+		("volume", metadata.volume.as_deref().map(braced)),
+		("number", metadata.issue.as_deref().map(braced)),
+		("pages", metadata.pages.as_deref().map(|pages| braced(&pages.replace(['-', '\u{2013}'], "--")))),
+		(publisher, metadata.publisher.as_deref().map(braced)),
+		("address", metadata.place.as_deref().map(braced)),
+		("year", date.map(|date| format!("{{{}}}", date.year))),
+		("month", date.and_then(|date| date.month).map(|month| MONTHS[month.number() as usize - 1].to_owned())),
+		// * Links are read verbatim, so they are not escaped.
+		("doi", metadata.doi.as_ref().map(|doi| format!("{{{doi}}}"))),
+		("url", source.url.as_ref().filter(|url| !url.is_empty()).map(|url| format!("{{{url}}}"))),
+		("isbn", metadata.isbn.as_deref().map(braced)),
+		("issn", metadata.issn.as_deref().map(braced)),
+		("language", metadata.language.as_deref().map(braced)),
+		("keywords", (!source.tags.is_empty()).then(|| braced(&source.tags.iter().map(|tag| &tag.name).join(", "))))
+	].into_iter().filter_map(|(name, value)| Some((name, value?))).collect()
+}
+
+/// Escapes characters with a meaning in (La)TeX, collapsing whitespace so that each value stays on one line.
+fn escape(text: &str) -> String {
+	let mut out = String::with_capacity(text.len());
+
+	for c in text.split_whitespace().join(" ").chars() {
+		match c {
+			'\\' => out.push_str("\\textbackslash{}"),
+			'~' => out.push_str("\\textasciitilde{}"),
+			'^' => out.push_str("\\textasciicircum{}"),
+			'{' | '}' | '&' | '%' | '$' | '#' | '_' => { out.push('\\'); out.push(c); },
+			c => out.push(c)
+		}
+	}
+
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn write_entries() {
+		let source = SourceImport::fixture().with_title("Short").with_date("March 2020")
+			.with_person(CreatorRole::Author, "Ada", "Lovelace").with_institution(CreatorRole::Author, "R&D Group")
+			.with_metadata(serde_json::json!({ "title": "Costs of 50% {more}", "publication": "Journal", "pages": "1-10" }));
+
+		assert_eq!(write_bibtex(&[Entry { key: "lovelace2020costs", source: &source }]), concat!(
+			"@article{lovelace2020costs,\n",
+			"  author = {Lovelace, Ada and {R\\&D Group}},\n",
+			"  title = {Costs of 50\\% \\{more\\}},\n",
+			"  journal = {Journal},\n",
+			"  pages = {1--10},\n",
+			"  year = {2020},\n",
+			"  month = mar\n",
+			"}\n"
+		));
+	}
+}
\ No newline at end of file
@@ -0,0 +1,72 @@
+use std::{fs, path::PathBuf};
+
+use console::style;
+
+use crate::{api::import::source::SourceImport, core::SourceRegistry, ProgramConfig, ProgramError};
+use crate::command::import::scan::vault::VaultIndex;
+
+use super::{bibtex::write_bibtex, csl::write_csl};
+
+const BIBTEX_NAME: &str = "bibliography.bib";
+const CSL_NAME: &str = "bibliography.json";
+
+#[derive(clap::Args, Debug)]
+pub struct BibliographyArgs {
+	/// Where to write the BibTeX file, by default `bibliography.bib` in the workspace.
+	#[arg(long)]
+	bib: Option<PathBuf>,
+
+	/// Where to write the CSL-JSON file, by default `bibliography.json` in the workspace.
+	#[arg(long)]
+	csl: Option<PathBuf>
+}
+
+/// A source along with the key it is cited by.
+pub(super) struct Entry<'a> {
+	pub key: &'a str,
+	pub source: &'a SourceImport
+}
+
+pub fn bibliography(config: &ProgramConfig, verbose: bool, args: BibliographyArgs) -> Result<(), ProgramError> {
+	let registry: SourceRegistry = SourceRegistry::load(&config.data_path).map_err(|error| {
+		eprintln!("{}: {}", style("Error").bold().red(), style("Unable to read the record of imported sources").bold());
+		eprintln!("{}: {error}", style("Reason").bold());
+
+		ProgramError::FilesystemError
+	})?;
+
+	let vault: VaultIndex = VaultIndex::new(&config.workspace_path).map_err(|error| {
+		eprintln!("{}: {}", style("Error").bold().red(), style("Unable to index existing notes").bold());
+		eprintln!("{}: {error}", style("Reason").bold());
+
+		ProgramError::FilesystemError
+	})?;
+
+	// * Only sources whose notes are still in the vault are included.
+	let mut entries: Vec<Entry> = registry.sources().iter()
+		.filter(|recorded| vault.find_source(&recorded.source.key).is_some())
+		.map(|recorded| Entry { key: &recorded.citation_key, source: &recorded.source })
+		.collect();
+
+	entries.sort_by(|a, b| a.key.cmp(b.key));
+
+	let outputs = [
+		(args.bib.unwrap_or_else(|| config.workspace_path.join(BIBTEX_NAME)), write_bibtex(&entries)),
+		(args.csl.unwrap_or_else(|| config.workspace_path.join(CSL_NAME)), write_csl(&entries))
+	];
+
+	for (path, content) in outputs {
+		if verbose { println!("Writing {}", path.to_string_lossy()); }
+
+		fs::write(&path, content).map_err(|error| {
+			eprintln!("{}: {}", style("Error").bold().red(), style(format!("Unable to write '{}'", path.to_string_lossy())).bold());
+			eprintln!("{}: {error}", style("Reason").bold());
+
+			ProgramError::FilesystemError
+		})?;
+	}
+
+	println!("\n{}: Wrote {} sources to the bibliography", style("Finished").bold().green(), entries.len());
+
+	Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,145 @@
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::api::{import::source::SourceImport, shared::{CreatorRole, Name}};
+
+use super::Entry;
+
+/// An item of a CSL-JSON bibliography.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Item<'a> {
+	id: &'a str,
+	#[serde(rename = "type")]
+	kind: &'static str,
+	title: &'a str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	title_short: Option<&'a str>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	author: Vec<ItemName<'a>>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	editor: Vec<ItemName<'a>>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	translator: Vec<ItemName<'a>>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	collection_editor: Vec<ItemName<'a>>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	container_author: Vec<ItemName<'a>>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	reviewed_author: Vec<ItemName<'a>>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	contributor: Vec<ItemName<'a>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	issued: Option<ItemDate>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	container_title: Option<&'a str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	collection_title: Option<&'a str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	volume: Option<&'a str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	issue: Option<&'a str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	page: Option<&'a str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	edition: Option<&'a str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	publisher: Option<&'a str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	publisher_place: Option<&'a str>,
+	#[serde(rename = "DOI", skip_serializing_if = "Option::is_none")]
+	doi: Option<&'a str>,
+	#[serde(rename = "ISBN", skip_serializing_if = "Option::is_none")]
+	isbn: Option<&'a str>,
+	#[serde(rename = "ISSN", skip_serializing_if = "Option::is_none")]
+	issn: Option<&'a str>,
+	#[serde(rename = "URL", skip_serializing_if = "Option::is_none")]
+	url: Option<&'a str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	language: Option<&'a str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	keyword: Option<String>
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum ItemName<'a> {
+	Full { family: &'a str, given: &'a str },
+	/// An organisation, or a name that could not be split.
+	Literal { literal: &'a str }
+}
+
+#[derive(Debug, Serialize)]
+struct ItemDate {
+	#[serde(rename = "date-parts")]
+	date_parts: [Vec<u32>; 1]
+}
+
+/// Writes a CSL-JSON bibliography of every entry.
+pub fn write_csl(entries: &[Entry]) -> String {
+	let items: Vec<Item> = entries.iter().map(|Entry { key, source }| item(key, source)).collect();
+
+	serde_json::to_string_pretty(&items).unwrap()
+}
+
+fn item_type(kind: &str) -> &'static str {
+	match kind {
+		"journalArticle" => "article-journal",
+		"magazineArticle" => "article-magazine",
+		"newspaperArticle" => "article-newspaper",
+		"book" => "book",
+		"bookSection" => "chapter",
+		"conferencePaper" => "paper-conference",
+		"encyclopediaArticle" => "entry-encyclopedia",
+		"dictionaryEntry" => "entry-dictionary",
+		"thesis" => "thesis",
+		"report" => "report",
+		"manuscript" => "manuscript",
+		"webpage" => "webpage",
+		"blogPost" => "post-weblog",
+		"forumPost" => "post",
+		_ => "document"
+	}
+}
+
+fn item<'a>(key: &'a str, source: &'a SourceImport) -> Item<'a> {
+	let metadata = &source.metadata;
+
+	let names = |role| source.creators_with_role(role).map(|creator| match &creator.name {
+		Name::Full(full) => ItemName::Full { family: &full.last, given: &full.first },
+		Name::Combined(combined) => ItemName::Literal { literal: combined }
+	}).collect::<Vec<_>>();
+
+	let issued = source.parse_date().ok().map(|date| ItemDate {
+		date_parts: [[Some(date.year), date.month.map(|month| month.number())].into_iter().flatten().collect()]
+	});
+
+	Item {
+		id: key,
+		kind: item_type(&source.kind),
+		title: metadata.title.as_deref().unwrap_or(&source.title),
+		title_short: metadata.short_title.as_deref(),
+		author: names(CreatorRole::Author),
+		editor: names(CreatorRole::Editor),
+		translator: names(CreatorRole::Translator),
+		collection_editor: names(CreatorRole::SeriesEditor),
+		container_author: names(CreatorRole::BookAuthor),
+		reviewed_author: names(CreatorRole::ReviewedAuthor),
+		contributor: names(CreatorRole::Contributor),
+		issued,
+		container_title: metadata.publication.as_deref(),
+		collection_title: metadata.series.as_deref(),
+		volume: metadata.volume.as_deref(),
+		issue: metadata.issue.as_deref(),
+		page: metadata.pages.as_deref(),
+		edition: metadata.edition.as_deref(),
+		publisher: metadata.publisher.as_deref(),
+		publisher_place: metadata.place.as_deref(),
+		doi: metadata.doi.as_deref(),
+		isbn: metadata.isbn.as_deref(),
+		issn: metadata.issn.as_deref(),
+		url: source.url.as_deref().filter(|url| !url.is_empty()),
+		language: metadata.language.as_deref(),
+		keyword: (!source.tags.is_empty()).then(|| source.tags.iter().map(|tag| &tag.name).join(", "))
+	}
+}
\ No newline at end of file
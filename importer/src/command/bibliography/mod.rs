@@ -0,0 +1,5 @@
+mod core;
+pub use core::*;
+
+mod bibtex;
+mod csl;
\ No newline at end of file
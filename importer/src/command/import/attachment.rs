@@ -0,0 +1,52 @@
+use std::{fs, io, path::{Path, PathBuf}};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::{api::import::annotation::AnnotationImage, util::directory::get_files_ext};
+
+const IMAGE_EXTENSION: &str = "png";
+
+#[derive(Debug)]
+pub enum AttachmentError {
+	Io(PathBuf, io::Error),
+	Decode(base64::DecodeError)
+}
+
+/// An annotation image to be copied into the attachments folder.
+#[derive(Debug, Clone)]
+pub struct AttachmentOutput {
+	pub path: PathBuf,
+	/// Whether an image was already present at the path.
+	pub existing: bool,
+	pub content: Vec<u8>
+}
+
+/// Returns the file name of the image belonging to an annotation.
+///
+/// Images are named by both the document and annotation keys, so that each annotation has exactly one image across imports.
+pub fn image_file_name(source_key: &str, annotation_key: &str) -> String {
+	format!("{source_key}-{annotation_key}.{IMAGE_EXTENSION}")
+}
+
+/// Reads the PNG data of an annotation image.
+pub fn load_image(image: &AnnotationImage) -> Result<Vec<u8>, AttachmentError> {
+	match image {
+		AnnotationImage::Path(path) => fs::read(path).map_err(|error| AttachmentError::Io(path.clone(), error)),
+		AnnotationImage::Data(data) => STANDARD.decode(data).map_err(AttachmentError::Decode)
+	}
+}
+
+/// Finds all images in the attachments folder belonging to a document, along with the keys of their annotations.
+///
+/// Images are recognised by their name alone, so any that are to be deleted should be confirmed first.
+pub fn document_images(dir: &Path, source_key: &str) -> Result<Vec<(String, PathBuf)>, io::Error> {
+	if !dir.is_dir() { return Ok(Vec::new()); }
+
+	let prefix = format!("{source_key}-");
+
+	Ok(get_files_ext(dir, IMAGE_EXTENSION)?.into_iter().filter_map(|path| {
+		let key = path.file_stem()?.to_str()?.strip_prefix(&prefix)?.to_owned();
+
+		Some((key, path))
+	}).collect())
+}
\ No newline at end of file
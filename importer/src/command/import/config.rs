@@ -0,0 +1,272 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{api::import::annotation::{AnnotationType, Colour}, core::KeyFormula, util::path::is_contained_path};
+
+use super::naming::{NamePattern, SourcePattern};
+
+/// Paths to user-provided note templates.
+/// 
+/// Any template left unset falls back to the built-in layout.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateConfig {
+	pub source: Option<PathBuf>,
+	/// The layout shared by all annotation notes, which per-type templates may extend.
+	pub annotation: Option<PathBuf>,
+	/// Templates for specific annotation types, keyed by type name.
+	#[serde(default)]
+	pub annotation_types: HashMap<AnnotationType, PathBuf>,
+	/// Template for the link stubs written to a document's other collections.
+	pub stub: Option<PathBuf>,
+	/// Template for combined literature notes, used in place of the source template when annotations are written inline.
+	pub literature: Option<PathBuf>
+}
+
+/// Patterns used to name generated notes.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct NamingConfig {
+	pub source: SourcePattern,
+	pub annotation: NamePattern
+}
+
+impl Default for NamingConfig {
+	fn default() -> Self {
+		Self {
+			source: SourcePattern::parse("{title}").unwrap(),
+			annotation: NamePattern::parse("{short_name} {key}").unwrap()
+		}
+	}
+}
+
+/// Controls which annotations are imported, and how.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct AnnotationConfig {
+	/// Annotation types that are not imported; existing notes for them are left untouched.
+	pub skip: Vec<AnnotationType>,
+	/// The folder (relative to the workspace) that images of image annotations are copied to.
+	pub attachments: PathBuf,
+	/// The meanings given to annotation colours.
+	pub colours: Vec<ColourMapping>,
+	/// How far (as a distance in RGB space) an annotation colour may be from a mapped colour and still match it.
+	pub colour_tolerance: f64,
+	pub style: AnnotationStyle
+}
+
+/// How annotation text is rendered by the built-in templates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnotationStyle {
+	/// Styled HTML, reproducing the annotation colour.
+	#[default]
+	Html,
+	/// Obsidian callouts, typed by the annotation's colour mapping.
+	Callout
+}
+
+/// The callout type used for annotations whose colour mapping does not specify one.
+const DEFAULT_CALLOUT: &str = "quote";
+
+impl AnnotationConfig {
+	/// Checks that the attachments folder stays within the workspace.
+	pub fn is_valid_attachments(&self) -> bool {
+		!self.attachments.as_os_str().is_empty() && is_contained_path(&self.attachments)
+	}
+
+	/// Checks that the folders of all colour mappings stay within the annotations directory.
+	pub fn is_valid_colours(&self) -> bool {
+		self.colours.iter().filter_map(|mapping| mapping.folder.as_deref()).all(is_contained_path)
+	}
+
+	/// Finds the mapping closest to a colour, if any is within tolerance.
+	pub fn colour_mapping(&self, colour: &Colour) -> Option<&ColourMapping> {
+		self.colours.iter()
+			.map(|mapping| (mapping, mapping.colour.distance(colour)))
+			.filter(|(_, distance)| *distance <= self.colour_tolerance)
+			.min_by(|(_, a), (_, b)| a.total_cmp(b))
+			.map(|(mapping, _)| mapping)
+	}
+
+	/// Determines the callout type an annotation of the given colour is rendered with, or `None` if callouts are not used.
+	pub fn callout(&self, colour: &Colour) -> Option<&str> {
+		if self.style != AnnotationStyle::Callout { return None; }
+
+		Some(self.colour_mapping(colour).and_then(|mapping| mapping.callout.as_deref()).unwrap_or(DEFAULT_CALLOUT))
+	}
+}
+
+impl Default for AnnotationConfig {
+	fn default() -> Self {
+		Self { skip: Vec::new(), attachments: PathBuf::from("Attachments"), colours: Vec::new(), colour_tolerance: 32.0, style: AnnotationStyle::default() }
+	}
+}
+
+/// The meaning of annotations made in a particular colour.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColourMapping {
+	pub colour: Colour,
+	/// A short name for the meaning (eg. "claim"), recorded in the annotation's frontmatter.
+	pub label: String,
+	/// A tag added to the annotation note.
+	pub tag: Option<String>,
+	/// The Obsidian callout type that templates may render the annotation with (eg. "quote" or "warning").
+	pub callout: Option<String>,
+	/// A subfolder of the annotations directory that new notes of this colour are placed in.
+	pub folder: Option<PathBuf>
+}
+
+/// How annotations are written to the vault.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputMode {
+	/// A note per annotation, each linking back to the source note.
+	#[default]
+	Separate,
+	/// A single literature note per document, with every annotation written inline.
+	Combined
+}
+
+/// How annotations are grouped within a combined literature note.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnotationGrouping {
+	#[default]
+	Page,
+	Colour
+}
+
+/// Controls the layout of the generated notes.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct OutputConfig {
+	pub mode: OutputMode,
+	pub group_by: AnnotationGrouping
+}
+
+/// Document metadata that can be written to the frontmatter of source notes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceField {
+	ItemType,
+	Title,
+	ShortTitle,
+	Authors,
+	/// Who a document without authors is attributed to (its editors, institutional creators, or "Anonymous"), as in its citations.
+	AttributedTo,
+	Editors,
+	SeriesEditors,
+	Translators,
+	BookAuthors,
+	ReviewedAuthors,
+	/// Contributors, along with creators in any role not listed separately.
+	Contributors,
+	Date,
+	Publication,
+	Volume,
+	Issue,
+	Pages,
+	Edition,
+	Series,
+	Publisher,
+	Place,
+	Doi,
+	Isbn,
+	Issn,
+	Url,
+	Language,
+	CitationKey,
+	DateAdded,
+	DateModified,
+	/// The full reference to the document, in the configured citation style.
+	Citation,
+	Tags
+}
+
+/// Controls which properties are written to the frontmatter of notes.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct PropertiesConfig {
+	/// The metadata written to source notes, in addition to the properties identifying them.
+	pub source: Vec<SourceField>
+}
+
+impl Default for PropertiesConfig {
+	fn default() -> Self {
+		Self { source: vec![SourceField::Authors, SourceField::AttributedTo, SourceField::Date, SourceField::Citation, SourceField::Tags] }
+	}
+}
+
+/// The style that references and in-text citations are formatted in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CitationStyle {
+	/// APA, 7th edition.
+	#[default]
+	Apa,
+	/// Chicago author-date, 17th edition.
+	Chicago,
+	Ieee
+}
+
+/// Controls the citations written to notes.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct CitationConfig {
+	pub style: CitationStyle,
+	/// The formula citation keys are generated by, for sources without a key pinned in Zotero.
+	pub key: KeyFormula
+}
+
+/// Controls how text from Zotero is converted into Markdown.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct TextConfig {
+	/// Lines of annotation comments that are promoted to headings.
+	pub headers: Vec<HeaderRule>
+}
+
+impl TextConfig {
+	/// Checks that all header levels are valid Markdown heading levels.
+	pub fn is_valid_headers(&self) -> bool {
+		self.headers.iter().all(|rule| (1..=6).contains(&rule.level))
+	}
+}
+
+impl Default for TextConfig {
+	fn default() -> Self {
+		let header = |name: &str| HeaderRule { name: name.to_owned(), level: 1 };
+
+		Self { headers: vec![header("Summary"), header("Quotable"), header("Aim")] }
+	}
+}
+
+/// A line that is promoted to a heading wherever it appears on its own.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeaderRule {
+	pub name: String,
+	pub level: u8
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn match_colour_mappings() {
+		let colour = |hex: &str| serde_json::from_value::<Colour>(serde_json::Value::from(hex)).unwrap();
+		let mapping = |hex: &str, label: &str| ColourMapping { colour: colour(hex), label: label.to_owned(), tag: None, callout: None, folder: None };
+
+		let config = AnnotationConfig { colours: vec![mapping("#ffd400", "claim"), mapping("#ff6666", "disagree")], ..AnnotationConfig::default() };
+		let label = |hex: &str| config.colour_mapping(&colour(hex)).map(|mapping| mapping.label.as_str());
+
+		assert_eq!(label("#ffd400"), Some("claim"));
+		assert_eq!(label("#ffcc10"), Some("claim"));
+		assert_eq!(label("#ff6060"), Some("disagree"));
+		assert_eq!(label("#5fb236"), None);
+	}
+}
\ No newline at end of file
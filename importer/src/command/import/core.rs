@@ -1,11 +1,16 @@
-use std::{env, fs::{self, File}, io::{self, Read, Seek}, path::{Path, PathBuf}};
+use std::{collections::{HashMap, HashSet}, env, fs, io, iter, path::{Path, PathBuf}};
 
 use console::style;
+use itertools::Itertools;
+use similar::TextDiff;
 
-use crate::{api::{import::{self, annotation::Annotation}, select::index}, core::{CollectionPath, LibraryCache}, util::versioned};
-use crate::{global::{ANNOTATIONS_PREFIX, API_VERSION}, ProgramConfig, ProgramError};
-use super::format::{annotation::{write_annotation, AnnnotationPersist, AnnotationExportError, AnnotationImportData, AnnotationTarget}, source::{write_source, SourceExportError, SourceImportData, SourcePersist, SourceTarget}, target::NTarget};
-use super::scan::{notes::{get_note_files, NoteFetchError}, persistent::{get_persistent_sections, FetchPersistentError}};
+use crate::{api::{import::{self, annotation::{Annotation, AnnotationType}}, select::index}, core::{CollectionResolver, LibraryCache, LibraryIndexFormatError, KeyAssignment, ResolveError, SourceRegistry}, util::{directory::get_files_ext, terminal::is_attended, transaction::{Transaction, TransactionError}, versioned}};
+use crate::{global::API_VERSION, ProgramConfig, ProgramError};
+use super::format::{annotation::{write_annotation, AnnnotationPersist, AnnotationExportError, AnnotationImportData, AnnotationTarget}, source::{write_source, InlineAnnotations, SourceExportError, SourceImportData, SourcePersist, SourceTarget}, stub::{write_stub, StubExportError, StubImportData, StubTarget}, template::{TemplateLoadError, Templates}};
+use super::config::OutputMode;
+use super::naming::{annotation_note_names, source_note_name, NamingError};
+use super::attachment::{document_images, image_file_name, load_image, AttachmentError, AttachmentOutput};
+use super::scan::{notes::{get_note_files, query_delete_files, AnnotationFile, NoteFetchError}, persistent::{FetchPersistentError, PersistedSections}, vault::VaultIndex};
 
 #[derive(clap::Args, Debug)]
 pub struct ImportArgs {
@@ -13,7 +18,15 @@ pub struct ImportArgs {
 	file: PathBuf,
 
 	#[arg(short, long)]
-	index: PathBuf
+	index: PathBuf,
+
+	/// Run the full import without modifying the vault, printing the changes that would be made instead.
+	#[arg(long)]
+	dry_run: bool,
+
+	/// Print a diff of each note's new content against its current content (dry runs only).
+	#[arg(long, requires = "dry_run")]
+	diff: bool
 }
 
 #[derive(Debug)]
@@ -34,41 +47,95 @@ impl From<FetchPersistentError> for ParsePersistsError {
 	}
 }
 
+/// The existing state of a note that is about to be written.
 #[derive(Debug)]
-struct NoteTarget {
-	pub file: File,
-	pub exists: bool,
-	pub persists: Vec<String>
+struct NoteSource {
+	path: PathBuf,
+	moved_from: Option<PathBuf>,
+	/// The current contents of the note, if it exists.
+	previous: Option<String>,
+	persists: Option<PersistedSections>
 }
 
-impl NoteTarget {
-	pub fn write<N: NTarget<Persist: From<String>>>(&mut self, data: N::Data, persist: N::Persist) -> Result<(), N::Error> {
-		let persist = if self.exists {
-			self.parse_persists().unwrap(); self.file.set_len(0).unwrap(); self.file.rewind().unwrap(); Some(self.persists[0].as_str())
-		} else { None };
-		
-		N::new(
-			&mut self.file,
-			data,
-			persist.map(|p| N::Persist::from(p.to_owned())) // TODO: Accept a `&str` here.
-		).write()
+impl NoteSource {
+	pub fn load(file: AnnotationFile) -> Result<Self, ParsePersistsError> {
+		let AnnotationFile { path, exists, moved_from } = file;
+
+		if !exists { return Ok(Self { path, moved_from, previous: None, persists: None }); }
+
+		let previous = fs::read_to_string(moved_from.as_ref().unwrap_or(&path))?;
+		let persists = PersistedSections::parse(&previous)?;
+
+		Ok(Self { path, moved_from, previous: Some(previous), persists: Some(persists) })
 	}
+}
 
-	pub fn parse_persists(&mut self) -> Result<(), ParsePersistsError> {
-		let mut data = String::new();
-		self.file.read_to_string(&mut data)?;
+/// A fully rendered note, ready to be written.
+#[derive(Debug)]
+struct NoteOutput {
+	path: PathBuf,
+	/// The previous location of the note, which is removed once the note is written.
+	moved_from: Option<PathBuf>,
+	previous: Option<String>,
+	content: String
+}
+
+/// The outcome of importing a single document.
+#[derive(Debug, Clone, Copy)]
+enum DocumentStatus {
+	Created,
+	Updated,
+	/// The import was declined by the user.
+	Skipped,
+	Failed(ProgramError)
+}
+
+/// The number of notes touched by a document import.
+#[derive(Debug, Clone, Copy, Default)]
+struct NoteCounts {
+	created: usize,
+	updated: usize,
+	removed: usize
+}
+
+#[derive(Debug)]
+struct DocumentReport {
+	/// The document title, or the export file name if the document could not be read.
+	name: String,
+	status: DocumentStatus,
+	notes: NoteCounts
+}
 
-		self.persists = get_persistent_sections(&data)?.into_iter().map(|s| s.to_owned()).collect();
-		Ok(())
+impl DocumentReport {
+	fn new(name: String, result: Result<(DocumentStatus, NoteCounts), ProgramError>) -> Self {
+		match result {
+			Ok((status, notes)) => Self { name, status, notes },
+			Err(ProgramError::UserExit) => Self { name, status: DocumentStatus::Skipped, notes: NoteCounts::default() },
+			Err(error) => Self { name, status: DocumentStatus::Failed(error), notes: NoteCounts::default() }
+		}
 	}
 }
 
-pub fn import(config: &ProgramConfig, verbose: bool, args: ImportArgs) -> Result<(), ProgramError> {
-	let ProgramConfig { workspace_path, .. } = config;
+/// State shared between all documents of a single import.
+struct ImportContext<'a> {
+	config: &'a ProgramConfig,
+	args: &'a ImportArgs,
+	verbose: bool,
+	templates: &'a Templates,
+	index: &'a index::User,
+	caches: &'a HashMap<i64, LibraryCache<'a>>
+}
 
-	let export_file: String = fs::read_to_string(args.file).unwrap();
+/// Reads and parses a single export file.
+fn load_export_file(path: &Path) -> Result<Vec<import::Export>, ProgramError> {
+	let export_file: String = fs::read_to_string(path).map_err(|e| {
+		eprintln!("{}: {}", style("Error").bold().red(), style("Failed to read export file").bold());
+		eprintln!("{}: Unable to read '{}' ({e})", style("Reason").bold(), path.to_string_lossy());
 
-	let export: import::Export = versioned::deserialize_json_str_track(API_VERSION, &export_file).map_err(|e| {
+		ProgramError::FilesystemError
+	})?;
+
+	let export: import::ExportFile = versioned::deserialize_json_str_track(API_VERSION, &export_file).map_err(|e| {
 		match e {
 			versioned::Error::InvalidVersion(version) => {
 				eprintln!("{}: {}", style("Error").bold().red(), style("Unsupported API version").bold());
@@ -85,9 +152,72 @@ pub fn import(config: &ProgramConfig, verbose: bool, args: ImportArgs) -> Result
 		}
 	})?;
 
+	Ok(export.into_documents())
+}
+
+pub fn import(config: &ProgramConfig, verbose: bool, args: ImportArgs) -> Result<(), ProgramError> {
+	let ProgramConfig { workspace_path, templates, collections, annotations, text, .. } = config;
+
+	if !collections.is_valid_unfiled() {
+		eprintln!("{}: {}", style("Error").bold().red(), style("Invalid unfiled folder").bold());
+		eprintln!("{}: The unfiled folder must be a relative path within the workspace", style("Reason").bold());
+
+		return Err(ProgramError::InvalidConfig);
+	}
+
+	if !annotations.is_valid_attachments() {
+		eprintln!("{}: {}", style("Error").bold().red(), style("Invalid attachments folder").bold());
+		eprintln!("{}: The attachments folder must be a non-empty relative path within the workspace", style("Reason").bold());
+
+		return Err(ProgramError::InvalidConfig);
+	}
+
+	if !annotations.is_valid_colours() {
+		eprintln!("{}: {}", style("Error").bold().red(), style("Invalid colour mapping folder").bold());
+		eprintln!("{}: Colour mapping folders must be relative paths within the annotations directory", style("Reason").bold());
+
+		return Err(ProgramError::InvalidConfig);
+	}
+
+	if !text.is_valid_headers() {
+		eprintln!("{}: {}", style("Error").bold().red(), style("Invalid header rule").bold());
+		eprintln!("{}: Header levels must be between 1 and 6", style("Reason").bold());
+
+		return Err(ProgramError::InvalidConfig);
+	}
+
+	let templates: Templates = Templates::load(templates).map_err(|e| {
+		eprintln!("{}: {}", style("Error").bold().red(), style("Failed to load note templates").bold());
+
+		match e {
+			TemplateLoadError::Io(path, error) => eprintln!("{}: Unable to read '{}' ({error})", style("Reason").bold(), path.to_string_lossy()),
+			TemplateLoadError::Syntax(error) => eprintln!("{}: {error:#}", style("Reason").bold())
+		}
+
+		ProgramError::InvalidTemplate
+	})?;
+
+	// * Determine which export files to read; a directory is read as a batch of its `.json` files.
+	let export_paths: Vec<PathBuf> = if args.file.is_dir() {
+		let mut paths = get_files_ext(&args.file, "json").map_err(|error| {
+			println!("Error reading export directory!");
+			println!("Filesystem IO error: {error}"); ProgramError::FilesystemError
+		})?;
+
+		paths.sort();
+		paths
+	} else {
+		vec![args.file.clone()]
+	};
+
 	// TODO: Deduplicate index handling code.
 	// ==================
-	let index_file: String = fs::read_to_string(args.index).unwrap();
+	let index_file: String = fs::read_to_string(&args.index).map_err(|e| {
+		eprintln!("{}: {}", style("Error").bold().red(), style("Failed to read index file").bold());
+		eprintln!("{}: Unable to read '{}' ({e})", style("Reason").bold(), args.index.to_string_lossy());
+
+		ProgramError::FilesystemError
+	})?;
 
 	let index: index::User = versioned::deserialize_json_str_track(API_VERSION, &index_file).map_err(|e| {
 		match e {
@@ -100,124 +230,576 @@ pub fn import(config: &ProgramConfig, verbose: bool, args: ImportArgs) -> Result
 				eprintln!("{}: {e}", style("Info").bold());
 			},
 		}
-		
+
 		ProgramError::BadIndexFormat
 	})?;
 
-	let library: &index::Library = index.libraries.iter().find(|lib| lib.id == export.source.library as i64).ok_or(ProgramError::BadIndexFormat)?;
+	let caches: HashMap<i64, LibraryCache> = index.libraries.iter().map(|library| Ok((library.id, LibraryCache::new(library)?)))
+		.collect::<Result<_, _>>()
+		.map_err(|_: LibraryIndexFormatError| ProgramError::BadIndexFormat)?;
+	// ==================
+
+	// * Index existing notes by their identifying properties.
+	let mut vault: VaultIndex = VaultIndex::new(workspace_path).map_err(|error| {
+		println!("Error indexing existing notes!");
+		println!("Filesystem IO error: {error}"); ProgramError::FilesystemError
+	})?;
+
+	let mut resolver: CollectionResolver = CollectionResolver::load(&config.collections, &config.data_path).map_err(|error| {
+		eprintln!("{}: {}", style("Error").bold().red(), style("Unable to read remembered collection choices").bold());
+		eprintln!("{}: {error}", style("Reason").bold());
 
-	let cache: LibraryCache = LibraryCache::new(library).map_err(|_| ProgramError::BadIndexFormat)?;
+		ProgramError::FilesystemError
+	})?;
 
-	let document = library.documents.iter().find(|doc| doc.id == export.source.id as i64).ok_or(ProgramError::BadIndexFormat)?;
-	let collection_path: CollectionPath = cache.get_collection(*document.collection_ids.get(0).ok_or(ProgramError::BadImportFormat)?).ok_or(ProgramError::BadImportFormat)?.get_path();
-	// ==================
+	let mut registry: SourceRegistry = SourceRegistry::load(&config.data_path).map_err(|error| {
+		eprintln!("{}: {}", style("Error").bold().red(), style("Unable to read the record of imported sources").bold());
+		eprintln!("{}: {error}", style("Reason").bold());
+
+		ProgramError::FilesystemError
+	})?;
+
+	let context = ImportContext { config, args: &args, verbose, templates: &templates, index: &index, caches: &caches };
+
+	// * Import every document, recording failures rather than stopping at them.
+
+	let mut reports: Vec<DocumentReport> = Vec::new();
+
+	for path in &export_paths {
+		let exports = match load_export_file(path) {
+			Ok(exports) => exports,
+			Err(error) => {
+				let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+				reports.push(DocumentReport::new(name, Err(error)));
+
+				continue;
+			}
+		};
+
+		let batch = export_paths.len() > 1 || exports.len() > 1;
+
+		for export in exports {
+			let name = export.source.title.clone();
+
+			if batch { println!("\n{} {}", style("Document:").bold(), style(&name).cyan()); }
+
+			reports.push(DocumentReport::new(name, import_document(&context, &mut vault, &mut resolver, &mut registry, export)));
+		}
+	}
+
+	// * Dry runs leave no trace, so choices made during them are asked again by the real import.
+	if !args.dry_run {
+		if let Err(error) = resolver.save() {
+			eprintln!("{}: Unable to remember collection choices ({error})", style("Warning").bold().yellow());
+		}
+	}
+
+	if !args.dry_run {
+		if let Err(error) = registry.save() {
+			eprintln!("{}: Unable to record imported sources ({error})", style("Warning").bold().yellow());
+		}
+	}
+
+	// * A single document keeps its original outcome; batches report every document and only fail if any document did.
+	if let [report] = reports.as_slice() {
+		return match report.status {
+			DocumentStatus::Skipped => Err(ProgramError::UserExit),
+			DocumentStatus::Failed(error) => Err(error),
+			_ => Ok(())
+		};
+	}
+
+	print_summary(&reports, args.dry_run);
+
+	match reports.iter().any(|report| matches!(report.status, DocumentStatus::Failed(_))) {
+		true => Err(ProgramError::IncompleteBatch),
+		false => Ok(())
+	}
+}
+
+fn print_summary(reports: &[DocumentReport], dry_run: bool) {
+	println!("\n{}:", style("Summary").underlined());
+	println!("{:<8} {:>7} {:>7} {:>7}  Document", "Status", "Created", "Updated", "Removed");
+
+	for DocumentReport { name, status, notes } in reports {
+		let status = match status {
+			DocumentStatus::Created => style("Created").green(),
+			DocumentStatus::Updated => style("Updated").cyan(),
+			DocumentStatus::Skipped => style("Skipped").yellow(),
+			DocumentStatus::Failed(_) => style("Failed").red()
+		};
+
+		println!("{:<8} {:>7} {:>7} {:>7}  {name}", status.bold(), notes.created, notes.updated, notes.removed);
+	}
+
+	let count = |f: fn(&DocumentStatus) -> bool| reports.iter().filter(|report| f(&report.status)).count();
+
+	println!(
+		"\n{} created, {} updated, {} skipped, {} failed{}",
+		count(|s| matches!(s, DocumentStatus::Created)),
+		count(|s| matches!(s, DocumentStatus::Updated)),
+		count(|s| matches!(s, DocumentStatus::Skipped)),
+		count(|s| matches!(s, DocumentStatus::Failed(_))),
+		if dry_run { " (dry run)" } else { "" }
+	);
+}
+
+/// Formats a path within the workspace as an Obsidian link target.
+fn workspace_link(workspace_path: &Path, path: &Path) -> String {
+	path.strip_prefix(workspace_path).unwrap_or(path).components().map(|component| component.as_os_str().to_string_lossy()).join("/")
+}
+
+fn import_document(context: &ImportContext, vault: &mut VaultIndex, resolver: &mut CollectionResolver, registry: &mut SourceRegistry, export: import::Export) -> Result<(DocumentStatus, NoteCounts), ProgramError> {
+	let ImportContext { config, args, verbose, templates, index, caches } = *context;
+	let ProgramConfig { workspace_path, naming, collections, annotations: annotation_config, output, text, properties, citations, .. } = config;
+
+	let missing = || {
+		eprintln!("{}: {}", style("Error").bold().red(), style("Document not found in index").bold());
+		eprintln!("{}: The index may be out of date; try exporting it again", style("Info").bold());
+
+		ProgramError::BadIndexFormat
+	};
+
+	let library: &index::Library = index.libraries.iter().find(|lib| lib.id == export.source.library as i64).ok_or_else(missing)?;
+	let cache: &LibraryCache = caches.get(&library.id).ok_or(ProgramError::BadIndexFormat)?;
+
+	let document = library.documents.iter().find(|doc| doc.id == export.source.id as i64).ok_or_else(missing)?;
+
+	// * Choose the collection to import into, if the document is in several (or none, for unfiled documents).
+	let collection_id: Option<i64> = resolver.resolve(library, cache, document).map_err(|e| match e {
+		ResolveError::UserExit => ProgramError::UserExit,
+		ResolveError::Unattended => {
+			eprintln!("{}: {}", style("Error").bold().red(), style("The document is in multiple collections, but no user is present to choose between them").bold());
+			eprintln!("{}: Rerun the import from a terminal, or configure a non-interactive collection policy", style("Help").cyan());
+
+			ProgramError::Unattended
+		},
+		ResolveError::UnknownCollection(_) => ProgramError::BadIndexFormat,
+		ResolveError::Interact(error) => { println!("Console interaction error: {error}"); ProgramError::InteractError }
+	})?;
+
+	let collection_dir = |id: Option<i64>| -> Result<PathBuf, ProgramError> {
+		match id {
+			Some(id) => Ok(cache.get_collection(id).ok_or(ProgramError::BadIndexFormat)?.get_path().into()),
+			None => Ok(collections.unfiled.clone())
+		}
+	};
+
+	// * The original collection path is recorded, as folder names are sanitized.
+	let collection_name: Option<String> = collection_id.and_then(|id| cache.get_collection(id)).map(|collection| collection.get_path().to_string());
+
+	// * Notes imported before the document was filed are moved out of the unfiled folder once it is.
+	let unfiled_path: PathBuf = workspace_path.join(&collections.unfiled);
+	let relocate_from: Option<&Path> = (collection_id.is_some() && !collections.unfiled.as_os_str().is_empty()).then_some(&unfiled_path);
+
+	let (source, attachments, notes, annotations) = export.into_parts();
+
+	// * Fallbacks for missing details are listed, so that the details can be filled in within Zotero.
+	let fallbacks = source.fallbacks();
+
+	if !fallbacks.is_empty() {
+		println!("{}: Details of the document are missing, so fallbacks were used:", style("Warning").bold().yellow());
+		for fallback in &fallbacks { println!(" - {fallback}"); }
+	}
+
+	// * Skipped annotation types are dropped entirely, with their keys kept so that their existing notes are left alone.
+	let (annotations, skipped): (Vec<Annotation>, Vec<Annotation>) = annotations.into_iter().partition(|annot| !annotation_config.skip.contains(&annot.kind));
+	let skipped_keys: HashSet<String> = skipped.into_iter().map(|annot| annot.key).collect();
+
+	// * Combined literature notes write every annotation inline, so no annotation notes are produced for them.
+	let combined = output.mode == OutputMode::Combined;
+
+	let (annotations, inline_annotations): (Vec<Annotation>, Vec<Annotation>) = match combined {
+		true => (Vec::new(), annotations),
+		false => (annotations, Vec::new())
+	};
+
+	// * Images of image annotations are copied into the attachments folder, and those of removed annotations are deleted (with confirmation, along with residual notes).
+	let attachments_path: PathBuf = workspace_path.join(&annotation_config.attachments);
+
+	let stale_images: Vec<PathBuf> = document_images(&attachments_path, &source.key).map_err(|error| {
+		println!("Error reading attachments folder!");
+		println!("Filesystem IO error: {error}"); ProgramError::FilesystemError
+	})?.into_iter().filter(|(key, _)| {
+		!skipped_keys.contains(key) && !annotations.iter().chain(&inline_annotations).any(|annotation| annotation.key == *key)
+	}).map(|(_, path)| path).collect();
+
+	let mut attachment_outputs: Vec<AttachmentOutput> = Vec::new();
+	let mut image_links: HashMap<String, String> = HashMap::new();
 
-	// * Load export file.
-	// TODO: Could be multiple attachments.
-	let import::Export { source, annotations } = export;
+	for annotation in annotations.iter().chain(&inline_annotations).filter(|annotation| annotation.kind == AnnotationType::Image) {
+		let image_path: PathBuf = attachments_path.join(image_file_name(&source.key, &annotation.key));
+
+		if let Some(image) = &annotation.image {
+			let content = load_image(image).map_err(|error| {
+				println!("Error loading image of annotation {}!", annotation.key);
+
+				match error {
+					AttachmentError::Io(path, error) => { println!("Unable to read '{}' ({error})", path.to_string_lossy()); ProgramError::FilesystemError },
+					AttachmentError::Decode(error) => { println!("Invalid image data: {error}"); ProgramError::InvalidAPIQuery }
+				}
+			})?;
+
+			attachment_outputs.push(AttachmentOutput { existing: image_path.exists(), path: image_path.clone(), content });
+		}
+
+		// * An image left over from a previous import is still embedded if Zotero no longer provides one.
+		if annotation.image.is_some() || image_path.exists() {
+			image_links.insert(annotation.key.clone(), workspace_link(workspace_path, &image_path));
+		}
+	}
+
+	// * Determine note names.
+	let KeyAssignment { key: citekey, conflict } = registry.citation_key(&source, &citations.key);
+
+	if let Some(other) = conflict {
+		println!("{}: The citation key '{citekey}' pinned in Zotero is already used by '{other}'", style("Warning").bold().yellow());
+	}
+
+	let (source_name, annotation_names) = match source_note_name(&naming.source, &source, &citekey).and_then(|source_name| {
+		Ok((source_name, annotation_note_names(&naming.annotation, &source, &citekey, annotations.iter())?))
+	}) {
+		Ok(val) => val,
+		Err(NamingError::UnsupportedPlaceholder(placeholder)) => {
+			eprintln!("{}: {}", style("Error").bold().red(), style("Invalid note naming pattern").bold());
+			eprintln!("{}: The placeholder '{placeholder}' cannot be used for this kind of note", style("Reason").bold());
+
+			return Err(ProgramError::NamingError);
+		}
+	};
 
 	// TODO: Need to improve this.
-	let workspace_path: String = workspace_path.join(TryInto::<PathBuf>::try_into(collection_path).map_err(|_| ProgramError::BadImportFormat)?).join(&source.title).to_str().unwrap().to_owned();
+	let document_path: String = workspace_path.join(collection_dir(collection_id)?).join(&source_name).to_str().unwrap().to_owned();
 
 	// * Determine current output directory contents, relative to the target output.
-	let files = match get_note_files(&workspace_path, &source.file_name(), annotations.into_iter(), |a| { format!("{} {}", source.short_name(), a.key) }) {
+	let named_annotations: Vec<(Annotation, String)> = annotations.into_iter().zip(annotation_names).collect();
+
+	let mut files = match get_note_files(vault, &document_path, relocate_from, &source.key, &source_name, named_annotations.into_iter(), |(a, name)| {
+		// * New notes are placed in the folder of their colour mapping, if it has one.
+		let folder = annotation_config.colour_mapping(&a.colour).and_then(|mapping| mapping.folder.clone()).unwrap_or_default();
+
+		(a.key.clone(), folder.join(name))
+	}) {
 		Ok(val) => val,
-		Err(e) => {
+		Err(NoteFetchError::Filesystem(error)) => {
 			println!("Error determining existing note structure!");
+			println!("Filesystem IO error: {error}"); return Err(ProgramError::FilesystemError);
+		}
+		Err(NoteFetchError::NameTaken(path)) => {
+			eprintln!("{}: {}", style("Error").bold().red(), style(format!("A note of another item already exists at {}", path.to_string_lossy())).bold());
+			eprintln!("{}: Rename or move that note, or change the note naming pattern", style("Help").cyan());
 
-			match e {
-				NoteFetchError::UnrecognizedSources => { return Err(ProgramError::UserExit); },
-				NoteFetchError::Interact(error) => { println!("Console interaction error: {error}"); return Err(error.into()) },
-				NoteFetchError::Filesystem(error) => { println!("Filesystem IO error: {error}"); return Err(ProgramError::FilesystemError); }
-			}
+			return Err(ProgramError::NamingError);
 		}
 	};
 
-	// * Delete residual (unknown) notes. This is subject to user confirmation in `get_note_files``
-	for residual in &files.residuals {
-		if let Err(e) = fs::remove_file(residual) {
-			println!("Failed to remove file: {}\nFilesystem IO error: {e}", residual.to_string_lossy());
+	files.residuals.retain(|path| {
+		vault.identity(path).and_then(|identity| identity.zotero_annotation_key.as_deref()).is_none_or(|key| !skipped_keys.contains(key))
+	});
+
+	// * Notes of annotations now written inline are merged into the literature note (keeping their persistent content) rather than treated as residuals.
+	let (merged, residuals): (Vec<PathBuf>, Vec<PathBuf>) = files.residuals.into_iter().partition(|path| {
+		vault.identity(path).and_then(|identity| identity.zotero_annotation_key.as_deref()).is_some_and(|key| inline_annotations.iter().any(|annotation| annotation.key == key))
+	});
+
+	files.residuals = residuals;
+
+	// * Residual (unknown) notes and images are only deleted with user confirmation, as images are only recognised by their name.
+	if !args.dry_run && (!files.residuals.is_empty() || !stale_images.is_empty()) {
+		if !is_attended() {
+			eprintln!("{}: {}", style("Error").bold().red(), style("Existing notes or images would be removed, but no user is present to confirm").bold());
+			eprintln!("{}: Rerun the import from a terminal, or inspect the changes with '--dry-run'", style("Help").cyan());
+
+			return Err(ProgramError::Unattended);
 		}
-	}
 
-	fn open_file<P: AsRef<Path>>(path: P) -> Result<fs::File, ProgramError> {
-		fs::File::options().read(true).write(true).open(path).map_err(|error| {
-			println!("Failed to open file!");
-			println!("Filesystem IO error: {error}"); ProgramError::FilesystemError
-		})
+		match query_delete_files(files.residuals.iter().chain(&stale_images)) {
+			Ok(true) => (),
+			Ok(false) => { println!("Import cancelled; no files were changed"); return Err(ProgramError::UserExit); },
+			Err(error) => { println!("Console interaction error: {error}"); return Err(error.into()) }
+		}
 	}
 
-	fn create_file<P: AsRef<Path>>(path: P) -> Result<fs::File, ProgramError> {
-		fs::File::create_new(path).map_err(|error| {
-			println!("Failed to create file!");
-			println!("Filesystem IO error: {error}"); ProgramError::FilesystemError
-		})
-	}
+	fn load_note(file: AnnotationFile) -> Result<NoteSource, ProgramError> {
+		let path = file.path.clone();
 
-	fn load_note(path: &PathBuf, exists: bool) -> Result<NoteTarget, ProgramError> {
-		Ok(NoteTarget { file: if exists { open_file(path)? } else { create_file(path)? }, exists, persists: Vec::new() })
+		NoteSource::load(file).map_err(|error| {
+			println!("Failed to load existing note {}!", path.to_string_lossy());
+
+			match error {
+				ParsePersistsError::Io(error) => { println!("Filesystem IO error: {error}"); ProgramError::FilesystemError },
+				ParsePersistsError::ParsePersists(error) => { println!("Malformed persistent sections: {error:?}"); ProgramError::MalformedNote }
+			}
+		})
 	}
 
 	if verbose { println!("[DEBUG] - Current directory: {}\n", env::current_dir().map(|p| p.to_string_lossy().into_owned()).unwrap_or("<UNKNOWN>".to_owned())); }
 
-	println!("{}: Beginning import", style("Info").bold());
+	let mut source_note: NoteSource = load_note(files.source)?;
 
-	fn log_note_output<P: AsRef<Path>>(path: P, exists: bool) {
-		let file_name = path.as_ref().file_name().unwrap();
+	// * Sections of merged notes are keyed by their annotation key, so that the literature note template places them with their annotation.
+	for path in &merged {
+		let Some(key) = vault.identity(path).and_then(|identity| identity.zotero_annotation_key.as_deref()) else { continue; };
+		let note = load_note(AnnotationFile { path: path.clone(), exists: true, moved_from: None })?;
 
-		if exists {
-			println!("{} - {}", style("U").bold().cyan(), file_name.to_string_lossy());
-		} else {
-			println!("{} - {}", style("C").bold().green(), file_name.to_string_lossy());
+		let sections = source_note.persists.get_or_insert_default();
+
+		for section in note.persists.iter().flat_map(|persists| persists.iter()) {
+			let name = match section.name {
+				Some(name) => format!("{key}-{name}"),
+				None => key.to_owned()
+			};
+
+			sections.insert_missing(name, section.content);
 		}
 	}
 
-	println!("{}:", style("Source").underlined());
-	log_note_output(&files.source.path, files.source.exists);
-	let mut source_target: NoteTarget = load_note(&files.source.path, files.source.exists)?;
+	// * Sections merged into the literature note by an earlier combined import are moved back into the notes of their annotations.
+	let annotation_notes: Vec<(Annotation, NoteSource)> = files.annotations.map(|((z, _), file)| -> Result<_, ProgramError> {
+		let mut note = load_note(file)?;
+
+		if let Some(sections) = source_note.persists.as_mut() {
+			let existed = note.persists.is_some();
+			let mut restored = note.persists.take().unwrap_or_default();
 
-	println!("{}:", style("Annotations").underlined());
-	let annotation_targets: Vec<(Annotation, NoteTarget)> = files.annotations.map(|(z, file)| -> Result<_, ProgramError> {
-		let path = PathBuf::from(&workspace_path).join(ANNOTATIONS_PREFIX).join(file.path).with_extension("md");
+			sections.restore_merged(&z.key, &mut restored);
+			note.persists = (existed || !restored.is_empty()).then_some(restored);
+		}
 
-		log_note_output(&path, file.exists);
-		Ok((z, load_note(&path, file.exists)?))
+		Ok((z, note))
 	}).collect::<Result<Vec<_>, _>>()?;
 
-	// * Write output notes.
+	// * Render output notes.
+
+	let mut content = String::new();
 
-	let persist = if source_target.exists { source_target.parse_persists().unwrap(); source_target.file.set_len(0).unwrap(); source_target.file.rewind().unwrap(); Some(source_target.persists[0].as_str()) } else { None };
 	if let Err(e) = write_source(SourceTarget {
-		file: &mut source_target.file,
-		data: SourceImportData { source: &source },
-		persist: persist.map(|s| SourcePersist { content_section: s.to_owned() })
-	}) {
+		out: &mut content,
+		data: SourceImportData {
+			source: &source,
+			attachments: &attachments,
+			collection: collection_name.as_deref(),
+			notes: &notes,
+			headers: &text.headers,
+			fields: &properties.source,
+			style: citations.style,
+			citekey: &citekey,
+			inline: combined.then_some(InlineAnnotations { annotations: &inline_annotations, grouping: output.group_by, images: &image_links, config: annotation_config })
+		},
+		persist: source_note.persists.map(|sections| SourcePersist { sections })
+	}, templates) {
 		println!("Error exporting source note!");
 
 		match e {
-			SourceExportError::Io(error) => { println!("Filesystem IO error: {error}"); return Err(ProgramError::FilesystemError); },
 			SourceExportError::PropertyDeserialize(error) => { println!("Note property formatting error: {error}"); return Err(ProgramError::YAMLDeserializeError); },
+			SourceExportError::Template(error) => { println!("Note template error: {error:#}"); return Err(ProgramError::TemplateError); },
+			SourceExportError::Persistent(error) => { println!("Note template produced malformed persistent sections: {error:?}"); return Err(ProgramError::TemplateError); },
+		}
+	}
+
+	let source_output = NoteOutput { path: source_note.path, moved_from: source_note.moved_from, previous: source_note.previous, content };
+
+	// * Link stubs are written to every other collection containing the document.
+	let mut stub_outputs: Vec<NoteOutput> = Vec::new();
+
+	if collections.stubs {
+		let target: String = workspace_link(workspace_path, &source_output.path.with_extension(""));
+
+		for id in document.collection_ids.iter().filter(|id| Some(**id) != collection_id) {
+			let path: PathBuf = workspace_path.join(collection_dir(Some(*id))?).join(format!("{source_name}.md"));
+
+			// * Never overwrite an unrelated note that happens to share the stub's name.
+			let previous = match (path.exists(), vault.identity(&path).and_then(|identity| identity.zotero_stub.as_deref())) {
+				(false, _) => None,
+				(true, Some(key)) if key == source.key => Some(fs::read_to_string(&path).map_err(|error| {
+					println!("Failed to load existing note {}!", path.to_string_lossy());
+					println!("Filesystem IO error: {error}"); ProgramError::FilesystemError
+				})?),
+				(true, _) => {
+					println!("{}: Skipping link stub {}, as an unrelated note already exists there", style("Warning").bold().yellow(), path.to_string_lossy());
+					continue;
+				}
+			};
+
+			let mut content = String::new();
+
+			if let Err(e) = write_stub(StubTarget { out: &mut content, data: StubImportData { source: &source, target: &target }, persist: None }, templates) {
+				println!("Error exporting link stub!");
+
+				match e {
+					StubExportError::PropertyDeserialize(error) => { println!("Note property formatting error: {error}"); return Err(ProgramError::YAMLDeserializeError); },
+					StubExportError::Template(error) => { println!("Note template error: {error:#}"); return Err(ProgramError::TemplateError); }
+				}
+			}
+
+			stub_outputs.push(NoteOutput { path, moved_from: None, previous, content });
 		}
 	}
 
-	for (annotation, mut target) in annotation_targets {
-		let persist = if target.exists { target.parse_persists().unwrap(); target.file.set_len(0).unwrap(); target.file.rewind().unwrap(); Some(target.persists[0].as_str()) } else { None };
+	// * Stubs left in collections the document is no longer in are removed.
+	let stale_stubs: Vec<PathBuf> = vault.stubs_of(&source.key).iter().filter(|path| !stub_outputs.iter().any(|stub| stub.path == **path)).cloned().collect();
+
+	let mut annotation_outputs: Vec<NoteOutput> = Vec::new();
+
+	for (annotation, note) in annotation_notes {
+		let image_link: Option<&str> = image_links.get(&annotation.key).map(|link| link.as_str());
+
+		let mut content = String::new();
 
 		if let Err(e) = write_annotation(AnnotationTarget {
-			file: &mut target.file,
-			data: AnnotationImportData { source: &source, annot: annotation },
-			persist: persist.map(|s| AnnnotationPersist { content_section: s.to_owned() })
-		}) {
+			out: &mut content,
+			data: AnnotationImportData {
+				source: &source,
+				source_name: &source_name,
+				colour: annotation_config.colour_mapping(&annotation.colour),
+				callout: annotation_config.callout(&annotation.colour),
+				annot: annotation,
+				image: image_link,
+				headers: &text.headers,
+				style: citations.style
+			},
+			persist: note.persists.map(|sections| AnnnotationPersist { sections })
+		}, templates) {
 			println!("Error exporting annotation note!");
 
 			match e {
-				AnnotationExportError::Io(error) => { println!("Filesystem IO error: {error}"); return Err(ProgramError::FilesystemError); },
 				AnnotationExportError::PropertyDeserialize(error) => { println!("Note property formatting error: {error}"); return Err(ProgramError::YAMLDeserializeError); },
+				AnnotationExportError::Template(error) => { println!("Note template error: {error:#}"); return Err(ProgramError::TemplateError); },
+				AnnotationExportError::Persistent(error) => { println!("Note template produced malformed persistent sections: {error:?}"); return Err(ProgramError::TemplateError); },
 			}
 		}
+
+		annotation_outputs.push(NoteOutput { path: note.path, moved_from: note.moved_from, previous: note.previous, content });
 	}
 
+	let status = match source_output.previous {
+		Some(_) => DocumentStatus::Updated,
+		None => DocumentStatus::Created
+	};
+
+	let notes = NoteCounts {
+		created: iter::once(&source_output).chain(&annotation_outputs).chain(&stub_outputs).filter(|note| note.previous.is_none()).count(),
+		updated: iter::once(&source_output).chain(&annotation_outputs).chain(&stub_outputs).filter(|note| note.previous.is_some()).count(),
+		removed: files.residuals.len() + merged.len() + stale_stubs.len()
+	};
+
+	// * Report planned changes.
+
+	if args.dry_run {
+		println!("{}: Beginning dry run; no changes will be made", style("Info").bold());
+	} else {
+		println!("{}: Beginning import", style("Info").bold());
+	}
+
+	fn log_note_output(note: &NoteOutput, diff: bool) {
+		let file_name = note.path.file_name().unwrap();
+
+		if let Some(from) = &note.moved_from {
+			println!("{} - {} (moved from {})", style("U").bold().cyan(), file_name.to_string_lossy(), from.to_string_lossy());
+		} else if note.previous.is_some() {
+			println!("{} - {}", style("U").bold().cyan(), file_name.to_string_lossy());
+		} else {
+			println!("{} - {}", style("C").bold().green(), file_name.to_string_lossy());
+		}
+
+		if diff { print_diff(note); }
+	}
+
+	fn print_diff(note: &NoteOutput) {
+		let previous = note.previous.as_deref().unwrap_or_default();
+		if previous == note.content { return; }
+
+		let path = note.path.to_string_lossy();
+		let diff = TextDiff::from_lines(previous, note.content.as_str());
+
+		for line in diff.unified_diff().context_radius(3).header(&path, &path).to_string().lines() {
+			match line.chars().next() {
+				Some('+') => println!("    {}", style(line).green()),
+				Some('-') => println!("    {}", style(line).red()),
+				Some('@') => println!("    {}", style(line).cyan()),
+				_ => println!("    {line}")
+			}
+		}
+	}
+
+	println!("{}:", style("Source").underlined());
+	log_note_output(&source_output, args.diff);
+
+	if !combined {
+		println!("{}:", style("Annotations").underlined());
+		for note in &annotation_outputs { log_note_output(note, args.diff); }
+	}
+
+	if !stub_outputs.is_empty() {
+		println!("{}:", style("Stubs").underlined());
+		for note in &stub_outputs { log_note_output(note, args.diff); }
+	}
+
+	if !attachment_outputs.is_empty() {
+		println!("{}:", style("Attachments").underlined());
+
+		for attachment in &attachment_outputs {
+			let file_name = attachment.path.file_name().unwrap_or_default().to_string_lossy();
+
+			match attachment.existing {
+				true => println!("{} - {file_name}", style("U").bold().cyan()),
+				false => println!("{} - {file_name}", style("C").bold().green())
+			}
+		}
+	}
+
+	if !files.residuals.is_empty() || !merged.is_empty() || !stale_stubs.is_empty() || !stale_images.is_empty() {
+		println!("{}:", style("Removed").underlined());
+		for residual in files.residuals.iter().chain(&stale_stubs).chain(&stale_images) { println!("{} - {}", style("D").bold().red(), residual.to_string_lossy()); }
+		for note in &merged { println!("{} - {} (merged into the literature note)", style("D").bold().red(), note.to_string_lossy()); }
+	}
+
+	if args.dry_run {
+		println!("\n{}: Dry run complete", style("Finished").bold().green());
+		return Ok((status, notes));
+	}
+
+	// * Apply all changes at once, reverting everything if any of them fail.
+
+	let mut transaction = Transaction::new();
+
+	// * Notes the import touches are re-indexed once it is committed, so that later documents of a batch see them.
+	let touched: Vec<PathBuf> = files.residuals.iter().chain(&merged).chain(&stale_stubs).cloned()
+		.chain(iter::once(&source_output).chain(&annotation_outputs).chain(&stub_outputs).flat_map(|note| iter::once(note.path.clone()).chain(note.moved_from.clone())))
+		.collect();
+
+	// * Folders left empty, such as that of a document moved out of Unfiled, are removed as well.
+	transaction.prune_empty_dirs(workspace_path);
+
+	for residual in files.residuals.iter().chain(&merged).chain(&stale_stubs).chain(&stale_images) { transaction.remove(residual); }
+	for attachment in attachment_outputs { transaction.write(attachment.path, attachment.content); }
+	for note in iter::once(source_output).chain(annotation_outputs).chain(stub_outputs) {
+		if let Some(from) = note.moved_from { transaction.remove(from); }
+		transaction.write(note.path, note.content.into_bytes());
+	}
+
+	if let Err(TransactionError { path, error, unrestored }) = transaction.commit() {
+		println!("Failed to write {}!", path.to_string_lossy());
+		println!("Filesystem IO error: {error}");
+
+		if unrestored.is_empty() {
+			println!("All changes have been reverted");
+		} else {
+			println!("{}: The following files could not be restored to their original state:", style("Warning").bold().yellow());
+			for path in unrestored { println!(" - {}", style(path.to_string_lossy()).cyan()); }
+		}
+
+		return Err(ProgramError::FilesystemError);
+	}
+
+	if let Err(error) = vault.refresh(touched) {
+		eprintln!("{}: Unable to re-index the written notes ({error})", style("Warning").bold().yellow());
+	}
+
+	// * Only sources whose notes were written are recorded for the bibliography.
+	registry.record(&source, &citekey);
+
 	println!("\n{}: Import complete", style("Finished").bold().green());
 
-	Ok(())
+	Ok((status, notes))
 }
\ No newline at end of file
@@ -1,72 +1,53 @@
-use std::{fs::File, io::{self, Write}};
-
 use serde::Serialize;
 
-use crate::{api::import::annotation::{Annotation, Colour}, api::import::source::SourceImport};
+use crate::{api::import::annotation::Annotation, api::import::source::SourceImport};
+
+use super::super::config::{CitationStyle, ColourMapping, HeaderRule};
 
-use super::target::NoteTarget;
+use super::{citation::inline_citation, rich_text::{promote_headers, rich_text_to_markdown}, target::NoteTarget, template::{NoteRenderError, TemplateKind, Templates}};
+use super::super::scan::persistent::{FetchPersistentError, PersistedSections};
 
 #[derive(Debug, Clone)]
 pub struct AnnotationImportData<'a> {
 	pub source: &'a SourceImport,
-	pub annot: Annotation
+	/// The file name of the source note, for linking.
+	pub source_name: &'a str,
+	pub annot: Annotation,
+	/// The meaning of the annotation's colour, if it has one.
+	pub colour: Option<&'a ColourMapping>,
+	/// The callout type to render the annotation with, if callouts are used.
+	pub callout: Option<&'a str>,
+	/// The link target of the annotation's image within the vault, for image annotations.
+	pub image: Option<&'a str>,
+	pub headers: &'a [HeaderRule],
+	pub style: CitationStyle
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct AnnnotationPersist {
-	pub content_section: String
-}
-
-impl Default for AnnnotationPersist {
-	fn default() -> Self {
-		Self {
-			content_section: "\n".to_owned()
-		}
-	}
+	pub sections: PersistedSections
 }
 
 pub type AnnotationTarget<'a> = NoteTarget<'a, AnnotationImportData<'a>, AnnnotationPersist>;
 
 #[derive(Debug, Clone, Serialize)]
 struct AnnotationProperties {
+	zotero_key: String,
+	zotero_annotation_key: String,
+	zotero_attachment_key: String,
 	source: String,
+	page: String,
+	page_index: u32,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	colour_label: Option<String>,
 	tags: Vec<String>
 }
 
-static HEADER_NAMES: &[&str] = &["Summary", "Quotable", "Aim"];
-
-pub fn fixup_headers(mut buffer: String) -> String {
-	let names: Vec<String> = HEADER_NAMES.iter().map(|s| { "\n".to_owned() + s + "\n" }).collect();
-
-	// * Check for any matches at the start of the buffer.
-	for name in &names {
-		if buffer.starts_with(&name[1..]) {
-			buffer = "# ".to_owned() + &buffer;
-			break;
-		}
-	}
-
-	// * Replace general matches
-	for name in names {
-		let mut replacement = name.clone();
-		replacement.insert_str(1, "# ");
-
-		buffer = buffer.replace(&name, &replacement);
-	}
-
-	buffer
-}
-
 #[derive(Debug)]
 pub enum AnnotationExportError {
-	Io(io::Error),
-	PropertyDeserialize(serde_yml::Error)
-}
-
-impl From<io::Error> for AnnotationExportError {
-	fn from(value: io::Error) -> Self {
-		Self::Io(value)
-	}
+	PropertyDeserialize(serde_yml::Error),
+	Template(minijinja::Error),
+	Persistent(FetchPersistentError)
 }
 
 impl From<serde_yml::Error> for AnnotationExportError {
@@ -75,50 +56,68 @@ impl From<serde_yml::Error> for AnnotationExportError {
 	}
 }
 
-pub fn write_annotation(target: AnnotationTarget) -> Result<(), AnnotationExportError> {
-	let AnnotationTarget { file, data, persist } = target;
-	let AnnotationImportData { source, annot } = data;
+impl From<NoteRenderError> for AnnotationExportError {
+	fn from(value: NoteRenderError) -> Self {
+		match value {
+			NoteRenderError::Template(error) => Self::Template(error),
+			NoteRenderError::Persistent(error) => Self::Persistent(error)
+		}
+	}
+}
+
+pub fn write_annotation(target: AnnotationTarget, templates: &Templates) -> Result<(), AnnotationExportError> {
+	let AnnotationTarget { out, data, persist } = target;
+	let AnnotationImportData { source, source_name, annot, colour, callout, image, headers, style } = data;
 
 	let props = AnnotationProperties {
-		source: format!("[[{}]]", source.file_name()),
-		tags: annot.tags.iter().map(|s| { s.name.replace(" ", "_") }).collect()
+		zotero_key: source.key.clone(),
+		zotero_annotation_key: annot.key.clone(),
+		zotero_attachment_key: annot.attachment.clone(),
+		source: format!("[[{source_name}]]"),
+		page: annot.position.page_label.clone(),
+		page_index: annot.position.page_index,
+		colour_label: colour.map(|mapping| mapping.label.clone()),
+		tags: annot.tags.iter().map(|s| s.name.as_str()).chain(colour.and_then(|mapping| mapping.tag.as_deref())).map(|s| { s.replace(" ", "_") }).collect()
 	};
 
-	let AnnnotationPersist { content_section: persist_sec } = persist.unwrap_or_default();
+	let AnnnotationPersist { sections } = persist.unwrap_or_default();
 
-	let mut buffer = annot.comment.as_ref().cloned().unwrap_or_default();
-	buffer = fixup_headers(buffer);
+	let buffer = promote_headers(&rich_text_to_markdown(annot.comment.as_deref().unwrap_or_default()), headers);
 
-	AnnotationNote {
+	let note = templates.render_note(TemplateKind::Annotation(annot.kind), AnnotationNote {
 		properties: &serde_yml::to_string(&props)?,
-		text: annot.text.as_ref().map(|s| s.as_str()).unwrap_or("N/A"),
-		colour: annot.colour,
-		persist: &persist_sec,
+		source,
+		source_name,
+		annotation: &annot,
+		link: &annot.open_link(),
+		citation: &inline_citation(source, style, Some(&annot.position.page_label)),
+		colour,
+		callout,
+		image,
+		persistent: &sections,
 		content: &buffer
-	}.write_to(file)?;
+	}, &sections)?;
+
+	out.push_str(&note);
 
 	Ok(())
 }
 
+/// Context made available to annotation note templates.
+#[derive(Serialize)]
 struct AnnotationNote<'a> {
 	properties: &'a str,
-	text: &'a str,
-	colour: Colour,
-	persist: &'a str,
+	source: &'a SourceImport,
+	source_name: &'a str,
+	annotation: &'a Annotation,
+	/// A link opening the annotation in Zotero.
+	link: &'a str,
+	/// A short citation of the annotation, pointing at its page.
+	citation: &'a str,
+	/// The meaning of the annotation's colour, if it has one.
+	colour: Option<&'a ColourMapping>,
+	callout: Option<&'a str>,
+	image: Option<&'a str>,
+	persistent: &'a PersistedSections,
 	content: &'a str
-}
-
-impl<'a> AnnotationNote<'a> {
-	pub fn write_to(self, out: &mut File) -> Result<(), io::Error> {
-		let Self {
-			properties,
-			text,
-			colour,
-			persist,
-			content
-		} = self;
-
-		// * Markdown does not affect the styled text in Obsidian, so we use HTML for the italics as well.
-		out.write_all(format!("---\n{properties}---\n\n\"<mark style=\"background-color: {colour};\"><i>{text}</i></mark>\"\n\n**Persistent Notes**\n\n---\n\n<!--SZO-Persist-Begin-->{persist}%%SZO-Persist-End%%\n\n---\n\n{content}").as_bytes())
-	}
 }
\ No newline at end of file
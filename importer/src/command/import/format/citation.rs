@@ -0,0 +1,476 @@
+use itertools::Itertools;
+
+use crate::api::{import::source::{Attribution, SourceImport, ANONYMOUS}, shared::{Creator, CreatorRole, Name}};
+
+use super::super::config::CitationStyle;
+use super::sanitize::escape_line;
+
+/// A part of a formatted reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+	Text(String),
+	/// Text set in italics, such as the title of a book or journal.
+	Emphasis(String),
+	Url(String)
+}
+
+/// A formatted reference, kept in pieces so that it can be written as either Markdown or plain text.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Reference(Vec<Piece>);
+
+impl Reference {
+	fn text<T: Into<String>>(&mut self, text: T) -> &mut Self {
+		self.0.push(Piece::Text(text.into()));
+		self
+	}
+
+	fn emphasis<T: Into<String>>(&mut self, text: T) -> &mut Self {
+		self.0.push(Piece::Emphasis(text.into()));
+		self
+	}
+
+	/// Adds italic text ending a sentence, with the full stop set upright.
+	fn emphasis_sentence(&mut self, text: &str) -> &mut Self {
+		let text = text.trim_end();
+		self.emphasis(text);
+
+		match text.ends_with(['.', '?', '!']) {
+			true => self,
+			false => self.text(".")
+		}
+	}
+
+	fn url<T: Into<String>>(&mut self, url: T) -> &mut Self {
+		self.0.push(Piece::Url(url.into()));
+		self
+	}
+
+	pub fn to_markdown(&self) -> String {
+		// * References are written at the start of a line, where a leading number could otherwise be read as a list marker.
+		self.0.iter().enumerate().map(|(index, piece)| match piece {
+			Piece::Text(text) => escape_line(text, index == 0),
+			Piece::Emphasis(text) => format!("*{}*", escape_line(text, false)),
+			// * Escaping would break the link, so it is delimited instead.
+			Piece::Url(url) => format!("<{url}>")
+		}).collect()
+	}
+
+	pub fn to_plain(&self) -> String {
+		self.0.iter().map(|piece| match piece {
+			Piece::Text(text) | Piece::Emphasis(text) | Piece::Url(text) => text.as_str()
+		}).collect()
+	}
+}
+
+/// How a document is referenced, as determined by its Zotero item type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReferenceKind {
+	/// A part of a periodical.
+	Article,
+	/// A part of an edited volume or proceedings.
+	Chapter,
+	/// A standalone work.
+	Book,
+	Other
+}
+
+impl ReferenceKind {
+	fn of(source: &SourceImport) -> Self {
+		match source.kind.as_str() {
+			"journalArticle" | "magazineArticle" | "newspaperArticle" => Self::Article,
+			"bookSection" | "conferencePaper" | "encyclopediaArticle" | "dictionaryEntry" => Self::Chapter,
+			"book" | "thesis" | "report" | "manuscript" => Self::Book,
+			_ => Self::Other
+		}
+	}
+}
+
+/// The parts of a document's metadata that references are built from.
+struct Details<'a> {
+	kind: ReferenceKind,
+	attribution: Attribution,
+	/// The creators the document is attributed to, in place of its authors.
+	authors: Vec<&'a Creator>,
+	editors: Vec<&'a Creator>,
+	year: String,
+	title: &'a str,
+	publication: Option<&'a str>,
+	volume: Option<&'a str>,
+	issue: Option<&'a str>,
+	pages: Option<String>,
+	edition: Option<&'a str>,
+	publisher: Option<&'a str>,
+	place: Option<&'a str>,
+	/// A DOI link, or otherwise the document's URL.
+	link: Option<String>
+}
+
+impl<'a> Details<'a> {
+	fn new(source: &'a SourceImport) -> Self {
+		let metadata = &source.metadata;
+
+		let link = match metadata.doi.as_deref() {
+			Some(doi) if doi.starts_with("http") => Some(doi.to_owned()),
+			Some(doi) => Some(format!("https://doi.org/{doi}")),
+			None => source.url.clone().filter(|url| !url.is_empty())
+		};
+
+		let (attribution, authors) = source.attribution();
+
+		// * Editors credited in place of authors are not credited again.
+		let editors = match attribution {
+			Attribution::Editors => Vec::new(),
+			_ => source.creators_with_role(CreatorRole::Editor).collect()
+		};
+
+		Self {
+			kind: ReferenceKind::of(source),
+			attribution,
+			authors,
+			editors,
+			year: source.year_label(),
+			title: metadata.title.as_deref().unwrap_or(&source.title),
+			publication: metadata.publication.as_deref(),
+			volume: metadata.volume.as_deref(),
+			issue: metadata.issue.as_deref(),
+			pages: metadata.pages.as_ref().map(|pages| pages.replace('-', "\u{2013}")),
+			edition: metadata.edition.as_deref(),
+			publisher: metadata.publisher.as_deref(),
+			place: metadata.place.as_deref(),
+			link
+		}
+	}
+
+	/// Formats the names of the creators the document is attributed to, or "Anonymous" if there are none.
+	fn names<F: Fn(&Creator) -> String>(&self, format: F) -> Vec<String> {
+		match self.authors.is_empty() {
+			true => vec![ANONYMOUS.to_owned()],
+			false => self.authors.iter().map(|creator| format(creator)).collect()
+		}
+	}
+
+	/// Marks editors credited in place of authors, choosing between the singular and plural form.
+	fn editor_role(&self, singular: &str, plural: &str) -> String {
+		match (self.attribution, self.authors.len()) {
+			(Attribution::Editors, 1) => singular.to_owned(),
+			(Attribution::Editors, _) => plural.to_owned(),
+			_ => String::new()
+		}
+	}
+
+	/// The place and publisher, as written by the Chicago and IEEE styles.
+	fn imprint(&self) -> Option<String> {
+		match (self.place, self.publisher) {
+			(Some(place), Some(publisher)) => Some(format!("{place}: {publisher}")),
+			(place, publisher) => publisher.or(place).map(|s| s.to_owned())
+		}
+	}
+}
+
+fn initials(first: &str) -> String {
+	first.split_whitespace().map(|name| {
+		name.split('-').filter_map(|part| part.chars().next()).map(|c| format!("{c}.")).join("-")
+	}).join(" ")
+}
+
+/// "Last, F. M."
+fn inverted_initials(creator: &Creator) -> String {
+	match &creator.name {
+		Name::Full(full) if !full.first.is_empty() => format!("{}, {}", full.last, initials(&full.first)),
+		name => name.to_string().trim().to_owned()
+	}
+}
+
+/// "F. M. Last"
+fn initials_first(creator: &Creator) -> String {
+	match &creator.name {
+		Name::Full(full) if !full.first.is_empty() => format!("{} {}", initials(&full.first), full.last),
+		name => name.to_string().trim().to_owned()
+	}
+}
+
+/// "Last, First"
+fn inverted(creator: &Creator) -> String {
+	match &creator.name {
+		Name::Full(full) if !full.first.is_empty() => format!("{}, {}", full.last, full.first),
+		name => name.to_string().trim().to_owned()
+	}
+}
+
+/// Joins names into a list, placing the conjunction before the last name (with a serial comma for three or more).
+fn join_names(names: &[String], conjunction: &str) -> String {
+	match names {
+		[] => String::new(),
+		[name] => name.clone(),
+		[first, second] => format!("{first} {conjunction} {second}"),
+		[rest @ .., last] => format!("{}, {conjunction} {last}", rest.join(", "))
+	}
+}
+
+/// Adds a full stop to the end of text, unless it already ends in punctuation.
+fn terminate(text: &str) -> String {
+	match text.trim_end().ends_with(['.', '?', '!']) {
+		true => text.trim_end().to_owned(),
+		false => format!("{}.", text.trim_end())
+	}
+}
+
+/// Formats the full reference to a document, as written in a bibliography.
+pub fn reference(source: &SourceImport, style: CitationStyle) -> Reference {
+	let details = Details::new(source);
+
+	match style {
+		CitationStyle::Apa => apa(&details),
+		CitationStyle::Chicago => chicago(&details),
+		CitationStyle::Ieee => ieee(&details)
+	}
+}
+
+fn apa(details: &Details) -> Reference {
+	let mut out = Reference::default();
+
+	// * APA lists up to twenty authors, then elides all but the last.
+	let names: Vec<String> = details.names(inverted_initials);
+	let authors = match names.len() {
+		0..=1 => names.concat(),
+		2..=20 => format!("{}, & {}", names[..names.len() - 1].join(", "), names[names.len() - 1]),
+		_ => format!("{}, . . . {}", names[..19].join(", "), names[names.len() - 1])
+	};
+
+	let date = format!("({}). ", details.year);
+	let italic_title = !matches!(details.kind, ReferenceKind::Article | ReferenceKind::Chapter);
+
+	out.text(format!("{} {date}", terminate(&format!("{authors}{}", details.editor_role(" (Ed.)", " (Eds.)")))));
+
+	match (italic_title, details.edition) {
+		(true, Some(edition)) => out.emphasis(details.title).text(format!(" ({edition} ed.). ")),
+		(true, None) => out.emphasis_sentence(details.title).text(" "),
+		(false, _) => out.text(format!("{} ", terminate(details.title)))
+	};
+
+	match details.kind {
+		ReferenceKind::Article => if let Some(publication) = details.publication {
+			out.emphasis(publication);
+
+			if let Some(volume) = details.volume { out.text(", ").emphasis(volume); }
+			if let Some(issue) = details.issue { out.text(format!("({issue})")); }
+			if let Some(pages) = &details.pages { out.text(format!(", {pages}")); }
+
+			out.text(". ");
+		},
+		ReferenceKind::Chapter => {
+			let editors: Vec<String> = details.editors.iter().map(|creator| initials_first(creator)).collect();
+
+			out.text("In ");
+
+			if !editors.is_empty() {
+				let role = if editors.len() == 1 { "Ed." } else { "Eds." };
+				out.text(format!("{} ({role}), ", join_names(&editors, "&")));
+			}
+
+			if let Some(publication) = details.publication { out.emphasis(publication); }
+			if let Some(pages) = &details.pages { out.text(format!(" (pp. {pages})")); }
+
+			out.text(". ");
+			if let Some(publisher) = details.publisher { out.text(format!("{} ", terminate(publisher))); }
+		},
+		ReferenceKind::Book => if let Some(publisher) = details.publisher { out.text(format!("{} ", terminate(publisher))); },
+		ReferenceKind::Other => if let Some(container) = details.publication.or(details.publisher) { out.text(format!("{} ", terminate(container))); }
+	}
+
+	if let Some(link) = &details.link { out.url(link); }
+
+	trim_end(out)
+}
+
+fn chicago(details: &Details) -> Reference {
+	let mut out = Reference::default();
+
+	// * The first author is inverted for alphabetizing, and long author lists are shortened to seven names.
+	let mut names: Vec<String> = details.names(|creator| creator.to_string());
+	if let (Some(first), Some(creator)) = (names.first_mut(), details.authors.first()) { *first = inverted(creator); }
+
+	let authors = match names.len() {
+		0..=10 => join_names(&names, "and"),
+		_ => {
+			names.truncate(7);
+			format!("{}, et al", names.join(", "))
+		}
+	};
+
+	let italic_title = matches!(details.kind, ReferenceKind::Book | ReferenceKind::Other) && details.publication.is_none();
+	let title = |out: &mut Reference| {
+		match italic_title {
+			true => out.emphasis_sentence(details.title),
+			false => out.text(format!("\u{201c}{}\u{201d}", terminate(details.title)))
+		};
+	};
+
+	out.text(format!("{} {}. ", terminate(&format!("{authors}{}", details.editor_role(", ed.", ", eds."))), details.year));
+	title(&mut out);
+
+	out.text(" ");
+
+	match details.kind {
+		ReferenceKind::Article => if let Some(publication) = details.publication {
+			out.emphasis(publication);
+
+			if let Some(volume) = details.volume { out.text(format!(" {volume}")); }
+			if let Some(issue) = details.issue { out.text(format!(" ({issue})")); }
+			if let Some(pages) = &details.pages { out.text(format!(": {pages}")); }
+
+			out.text(". ");
+		},
+		ReferenceKind::Chapter => {
+			out.text("In ");
+			if let Some(publication) = details.publication { out.emphasis(publication); }
+
+			let editors: Vec<String> = details.editors.iter().map(|creator| creator.to_string()).collect();
+			if !editors.is_empty() { out.text(format!(", edited by {}", join_names(&editors, "and"))); }
+			if let Some(pages) = &details.pages { out.text(format!(", {pages}")); }
+
+			out.text(". ");
+			if let Some(imprint) = details.imprint() { out.text(format!("{} ", terminate(&imprint))); }
+		},
+		ReferenceKind::Book => if let Some(imprint) = details.imprint() { out.text(format!("{} ", terminate(&imprint))); },
+		ReferenceKind::Other => if let Some(container) = details.publication.or(details.publisher) { out.emphasis_sentence(container).text(" "); }
+	}
+
+	if let Some(link) = &details.link { out.url(link).text("."); }
+
+	trim_end(out)
+}
+
+fn ieee(details: &Details) -> Reference {
+	let mut out = Reference::default();
+
+	let names: Vec<String> = details.names(initials_first);
+	let role = details.editor_role(", Ed.", ", Eds.");
+
+	// * IEEE lists up to six authors.
+	match names.len() {
+		0..=6 => out.text(format!("{}{role}, ", join_names(&names, "and"))),
+		_ => out.text(format!("{} et al.{role}, ", names[0]))
+	};
+
+	let edition = details.edition.map(|edition| format!("{edition} ed."));
+
+	match details.kind {
+		ReferenceKind::Article => {
+			out.text(format!("\u{201c}{},\u{201d} ", details.title));
+			if let Some(publication) = details.publication { out.emphasis(publication).text(", "); }
+
+			let parts = [
+				details.volume.map(|volume| format!("vol. {volume}")),
+				details.issue.map(|issue| format!("no. {issue}")),
+				details.pages.as_ref().map(|pages| format!("pp. {pages}")),
+				Some(details.year.clone())
+			];
+
+			out.text(parts.into_iter().flatten().join(", "));
+		},
+		ReferenceKind::Chapter => {
+			out.text(format!("\u{201c}{},\u{201d} in ", details.title));
+			if let Some(publication) = details.publication { out.emphasis(publication); }
+
+			let editors: Vec<String> = details.editors.iter().map(|creator| initials_first(creator)).collect();
+			if !editors.is_empty() { out.text(format!(", {}, {}", join_names(&editors, "and"), if editors.len() == 1 { "Ed." } else { "Eds." })); }
+
+			out.text(". ");
+
+			let parts = [details.imprint(), Some(details.year.clone()), details.pages.as_ref().map(|pages| format!("pp. {pages}"))];
+			out.text(parts.into_iter().flatten().join(", "));
+		},
+		ReferenceKind::Book | ReferenceKind::Other => {
+			out.emphasis(details.title);
+			if let Some(edition) = &edition { out.text(format!(", {edition}")); }
+			out.text(". ");
+
+			let parts = [details.imprint().or(details.publication.map(|s| s.to_owned())), Some(details.year.clone())];
+			out.text(parts.into_iter().flatten().join(", "));
+		}
+	}
+
+	match (&details.link, details.kind) {
+		(Some(link), _) if link.starts_with("https://doi.org/") => { out.text(", doi: ").text(link.trim_start_matches("https://doi.org/")).text("."); },
+		(Some(link), _) => { out.text(". [Online]. Available: ").url(link); },
+		(None, _) => { out.text("."); }
+	}
+
+	trim_end(out)
+}
+
+/// Removes trailing whitespace left by the last piece of a reference.
+fn trim_end(mut reference: Reference) -> Reference {
+	if let Some(Piece::Text(text)) = reference.0.last_mut() {
+		text.truncate(text.trim_end().len());
+	}
+
+	reference
+}
+
+/// Formats a short in-text citation of a document (eg. "Smith et al., 2021, p. 12"), optionally pointing at a page.
+///
+/// As IEEE citations are numbered within a particular document, IEEE uses the same author-date form as APA.
+pub fn inline_citation(source: &SourceImport, style: CitationStyle, page: Option<&str>) -> String {
+	let names: Vec<String> = source.attribution().1.iter().map(|creator| creator.name.last().to_owned()).collect();
+	let year = source.year_label();
+
+	let authors = match (style, names.as_slice()) {
+		(_, []) => ANONYMOUS.to_owned(),
+		(_, [name]) => name.clone(),
+		(CitationStyle::Apa, [first, second]) => format!("{first} & {second}"),
+		(_, [first, second]) => format!("{first} and {second}"),
+		(CitationStyle::Chicago, [_, _, _]) => join_names(&names, "and"),
+		(_, [first, ..]) => format!("{first} et al.")
+	};
+
+	match style {
+		CitationStyle::Chicago => match page {
+			Some(page) => format!("{authors} {year}, {page}"),
+			None => format!("{authors} {year}")
+		},
+		CitationStyle::Apa | CitationStyle::Ieee => match page {
+			Some(page) => format!("{authors}, {year}, p. {page}"),
+			None => format!("{authors}, {year}")
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn source(kind: &str, authors: &[(&str, &str)], metadata: serde_json::Value) -> SourceImport {
+		let source = SourceImport::fixture().with_kind(kind).with_title("Short").with_date("2021").with_metadata(metadata);
+		authors.iter().fold(source, |source, (first, last)| source.with_person(CreatorRole::Author, first, last))
+	}
+
+	#[test]
+	fn format_references() {
+		let article = source("journalArticle", &[("Jane Q.", "Smith"), ("John", "Doe"), ("Ann", "Lee")], serde_json::json!({
+			"title": "A study of things", "publication": "Journal of Things", "volume": "12", "issue": "3", "pages": "45-67", "doi": "10.1000/xyz"
+		}));
+
+		assert_eq!(reference(&article, CitationStyle::Apa).to_plain(), "Smith, J. Q., Doe, J., & Lee, A. (2021). A study of things. Journal of Things, 12(3), 45\u{2013}67. https://doi.org/10.1000/xyz");
+		assert_eq!(reference(&article, CitationStyle::Apa).to_markdown(), "Smith, J. Q., Doe, J., \\& Lee, A. (2021). A study of things. *Journal of Things*, *12*(3), 45\u{2013}67. <https://doi.org/10.1000/xyz>");
+		assert_eq!(reference(&article, CitationStyle::Chicago).to_plain(), "Smith, Jane Q., John Doe, and Ann Lee. 2021. \u{201c}A study of things.\u{201d} Journal of Things 12 (3): 45\u{2013}67. https://doi.org/10.1000/xyz.");
+		assert_eq!(reference(&article, CitationStyle::Ieee).to_plain(), "J. Q. Smith, J. Doe, and A. Lee, \u{201c}A study of things,\u{201d} Journal of Things, vol. 12, no. 3, pp. 45\u{2013}67, 2021, doi: 10.1000/xyz.");
+
+		let book = source("book", &[("Ian", "Goodfellow")], serde_json::json!({ "title": "Deep Learning", "publisher": "MIT Press", "place": "Cambridge, MA" }));
+
+		assert_eq!(reference(&book, CitationStyle::Apa).to_plain(), "Goodfellow, I. (2021). Deep Learning. MIT Press.");
+		assert_eq!(reference(&book, CitationStyle::Chicago).to_plain(), "Goodfellow, Ian. 2021. Deep Learning. Cambridge, MA: MIT Press.");
+		assert_eq!(reference(&book, CitationStyle::Ieee).to_plain(), "I. Goodfellow, Deep Learning. Cambridge, MA: MIT Press, 2021.");
+
+		let edited = source("book", &[], serde_json::json!({ "title": "Collected Papers" })).with_person(CreatorRole::Editor, "Ann", "Lee");
+
+		assert_eq!(reference(&edited, CitationStyle::Apa).to_plain(), "Lee, A. (Ed.). (2021). Collected Papers.");
+		assert_eq!(reference(&edited, CitationStyle::Chicago).to_plain(), "Lee, Ann, ed. 2021. Collected Papers.");
+		assert_eq!(reference(&SourceImport { date: String::new(), ..source("webpage", &[], serde_json::json!({})) }, CitationStyle::Apa).to_plain(), "Anonymous. (n.d.). Short.");
+
+		assert_eq!(inline_citation(&article, CitationStyle::Apa, Some("12")), "Smith et al., 2021, p. 12");
+		assert_eq!(inline_citation(&article, CitationStyle::Chicago, None), "Smith, Doe, and Lee 2021");
+		assert_eq!(inline_citation(&book, CitationStyle::Ieee, Some("3")), "Goodfellow, 2021, p. 3");
+	}
+}
\ No newline at end of file
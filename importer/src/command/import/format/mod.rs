@@ -1,3 +1,8 @@
 pub mod source;
 pub mod annotation;
-pub mod target;
\ No newline at end of file
+pub mod stub;
+pub mod target;
+pub mod template;
+pub mod sanitize;
+pub mod rich_text;
+pub mod citation;
\ No newline at end of file
@@ -0,0 +1,507 @@
+use std::mem;
+
+use itertools::Itertools;
+
+use super::super::config::HeaderRule;
+use super::sanitize::{escape_line, escape_markdown, escape_markdown_inline};
+
+/// Elements whose presence marks text as Zotero rich text rather than plain text.
+const RICH_TEXT_ELEMENTS: &[&str] = &[
+	"p", "div", "br", "hr", "span", "a", "blockquote", "pre", "code", "ul", "ol", "li",
+	"b", "strong", "i", "em", "u", "s", "del", "strike", "mark", "sup", "sub",
+	"h1", "h2", "h3", "h4", "h5", "h6"
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token<'a> {
+	Text(&'a str),
+	/// An opening (or self-closing) tag, with its attributes.
+	Open(String, Vec<(String, String)>),
+	Close(String)
+}
+
+/// Splits HTML into text and tags, dropping comments; a `<` that does not start a tag is kept as text.
+fn tokenize(html: &str) -> Vec<Token<'_>> {
+	let mut tokens = Vec::new();
+	let mut rest = html;
+
+	while !rest.is_empty() {
+		let Some(start) = rest.find('<') else {
+			tokens.push(Token::Text(rest));
+			break;
+		};
+
+		if start > 0 { tokens.push(Token::Text(&rest[..start])); }
+		rest = &rest[start..];
+
+		if let Some(comment) = rest.strip_prefix("<!--") {
+			rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+			continue;
+		}
+
+		match parse_tag(rest) {
+			Some((token, len)) => { tokens.push(token); rest = &rest[len..]; },
+			None => { tokens.push(Token::Text("<")); rest = &rest[1..]; }
+		}
+	}
+
+	tokens
+}
+
+/// Parses the tag at the start of the input, returning it along with its length.
+fn parse_tag(input: &str) -> Option<(Token<'_>, usize)> {
+	// * A '>' within a quoted attribute value does not end the tag.
+	let mut quote: Option<char> = None;
+
+	let (end, _) = input.char_indices().skip(1).find(|(_, c)| match quote {
+		Some(q) => { if *c == q { quote = None; } false },
+		None => { if matches!(c, '"' | '\'') { quote = Some(*c); } *c == '>' }
+	})?;
+
+	let inner = &input[1..end];
+	let (closing, inner) = match inner.strip_prefix('/') {
+		Some(inner) => (true, inner),
+		None => (false, inner)
+	};
+
+	if !inner.starts_with(|c: char| c.is_ascii_alphabetic()) { return None; }
+
+	let name_len = inner.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(inner.len());
+	let name = inner[..name_len].to_ascii_lowercase();
+
+	let token = match closing {
+		true => Token::Close(name),
+		false => Token::Open(name, parse_attributes(&inner[name_len..]))
+	};
+
+	Some((token, end + 1))
+}
+
+fn parse_attributes(mut input: &str) -> Vec<(String, String)> {
+	let mut attributes = Vec::new();
+
+	loop {
+		input = input.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+		if input.is_empty() { break; }
+
+		let name_len = input.find(|c: char| c.is_whitespace() || c == '=' || c == '/').unwrap_or(input.len());
+		let name = input[..name_len].to_ascii_lowercase();
+		input = input[name_len..].trim_start();
+
+		let value = match input.strip_prefix('=').map(str::trim_start) {
+			Some(rest) => {
+				let (value, remainder) = match rest.chars().next() {
+					Some(q @ ('"' | '\'')) => {
+						let body = &rest[1..];
+						let close = body.find(q).unwrap_or(body.len());
+
+						(&body[..close], body.get(close + 1..).unwrap_or_default())
+					},
+					_ => rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len()))
+				};
+
+				input = remainder;
+				decode_entities(value)
+			},
+			None => String::new()
+		};
+
+		attributes.push((name, value));
+	}
+
+	attributes
+}
+
+/// Decodes the named entities Zotero produces, along with all numeric character references.
+fn decode_entities(text: &str) -> String {
+	let mut out = String::with_capacity(text.len());
+	let mut rest = text;
+
+	while let Some(start) = rest.find('&') {
+		out.push_str(&rest[..start]);
+		rest = &rest[start + 1..];
+
+		let decoded = rest.find(';').filter(|end| *end <= 10).and_then(|end| Some((decode_entity(&rest[..end])?, end + 1)));
+
+		match decoded {
+			Some((c, len)) => { out.push(c); rest = &rest[len..]; },
+			None => out.push('&')
+		}
+	}
+
+	out.push_str(rest);
+	out
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+	match entity {
+		"amp" => Some('&'),
+		"lt" => Some('<'),
+		"gt" => Some('>'),
+		"quot" => Some('"'),
+		"apos" => Some('\''),
+		"nbsp" => Some('\u{a0}'),
+		_ => {
+			let number = entity.strip_prefix('#')?;
+
+			let code = match number.strip_prefix(['x', 'X']) {
+				Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+				None => number.parse().ok()?
+			};
+
+			char::from_u32(code)
+		}
+	}
+}
+
+/// The delimiters that inline elements are written with.
+fn inline_delimiters(name: &str) -> Option<(&'static str, &'static str)> {
+	match name {
+		"b" | "strong" => Some(("**", "**")),
+		"i" | "em" => Some(("*", "*")),
+		"s" | "del" | "strike" => Some(("~~", "~~")),
+		"mark" => Some(("==", "==")),
+		// * Markdown has no syntax for these, but Obsidian renders inline HTML.
+		"u" => Some(("<u>", "</u>")),
+		"sup" => Some(("<sup>", "</sup>")),
+		"sub" => Some(("<sub>", "</sub>")),
+		_ => None
+	}
+}
+
+fn attribute<'a>(attributes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+	attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+}
+
+/// Text that is written verbatim rather than escaped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verbatim {
+	Code,
+	CodeBlock,
+	Math,
+	MathBlock
+}
+
+struct Converter {
+	out: String,
+	/// The inline content of the block being built.
+	line: String,
+	/// Whether newlines within text are kept as line breaks, rather than collapsed as whitespace.
+	line_breaks: bool,
+	heading_offset: usize,
+	/// The heading level of the block being built.
+	heading: Option<usize>,
+	/// The marker of a list item whose first block has not been written yet.
+	marker: Option<String>,
+	/// Open lists, holding the next item number of ordered lists.
+	lists: Vec<Option<u32>>,
+	quote_depth: usize,
+	/// The targets of open links, if they have one.
+	links: Vec<Option<String>>,
+	/// Verbatim text being collected, along with the element that started it.
+	verbatim: Option<(Verbatim, String, String)>,
+	/// Whether the last block written was in a list, as list items are not separated by blank lines.
+	last_in_list: bool,
+	/// The quote depth of the last block written, which the blank line after it continues.
+	last_quote_depth: usize
+}
+
+impl Converter {
+	fn new(line_breaks: bool, heading_offset: usize) -> Self {
+		Self {
+			out: String::new(),
+			line: String::new(),
+			line_breaks,
+			heading_offset,
+			heading: None,
+			marker: None,
+			lists: Vec::new(),
+			quote_depth: 0,
+			links: Vec::new(),
+			verbatim: None,
+			last_in_list: false,
+			last_quote_depth: 0
+		}
+	}
+
+	fn open(&mut self, name: &str, attributes: &[(String, String)]) {
+		if let Some((_, _, text)) = &mut self.verbatim {
+			if name == "br" { text.push('\n'); }
+			return;
+		}
+
+		let math = attribute(attributes, "class").is_some_and(|class| class.split_whitespace().any(|class| class == "math"));
+
+		match name {
+			"p" | "div" | "table" | "tr" => self.flush(),
+			"h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+				self.flush();
+				self.heading = name[1..].parse().ok();
+			},
+			"br" => self.line.push('\n'),
+			"hr" => {
+				self.flush();
+				self.write_block("---");
+			},
+			"ul" | "ol" => {
+				self.flush();
+				self.lists.push((name == "ol").then(|| attribute(attributes, "start").and_then(|start| start.parse().ok()).unwrap_or(1)));
+			},
+			"li" => {
+				self.flush();
+
+				self.marker = Some(match self.lists.last_mut() {
+					Some(Some(number)) => { let current = *number; *number = current.saturating_add(1); format!("{current}. ") },
+					_ => "- ".to_owned()
+				});
+			},
+			"blockquote" => {
+				self.flush();
+				self.quote_depth += 1;
+			},
+			"pre" => {
+				self.flush();
+				self.verbatim = Some((if math { Verbatim::MathBlock } else { Verbatim::CodeBlock }, name.to_owned(), String::new()));
+			},
+			"code" => self.verbatim = Some((Verbatim::Code, name.to_owned(), String::new())),
+			"span" if math => self.verbatim = Some((Verbatim::Math, name.to_owned(), String::new())),
+			"a" => {
+				let href = attribute(attributes, "href").filter(|href| !href.is_empty()).map(|href| href.to_owned());
+				if href.is_some() { self.line.push('['); }
+
+				self.links.push(href);
+			},
+			_ => if let Some((open, _)) = inline_delimiters(name) { self.line.push_str(open); }
+		}
+	}
+
+	fn close(&mut self, name: &str) {
+		if let Some((_, element, _)) = &self.verbatim {
+			if element != name { return; }
+
+			if let Some((kind, _, text)) = self.verbatim.take() { self.write_verbatim(kind, &text); }
+			return;
+		}
+
+		match name {
+			"p" | "div" | "table" | "tr" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => self.flush(),
+			"li" => {
+				self.flush();
+				self.marker = None;
+			},
+			"ul" | "ol" => {
+				self.flush();
+				self.lists.pop();
+			},
+			"blockquote" => {
+				self.flush();
+				self.quote_depth = self.quote_depth.saturating_sub(1);
+			},
+			"a" => if let Some(Some(href)) = self.links.pop() {
+				self.line.push_str(&format!("]({})", href.replace(' ', "%20").replace('(', "%28").replace(')', "%29")));
+			},
+			_ => if let Some((open, close)) = inline_delimiters(name) {
+				// * Delimiters must hug the text they apply to, and are dropped entirely around empty elements.
+				let trailing = self.line.split_off(self.line.trim_end().len());
+
+				match self.line.strip_suffix(open) {
+					Some(rest) => self.line.truncate(rest.len()),
+					None => self.line.push_str(close)
+				}
+
+				self.line.push_str(&trailing);
+			}
+		}
+	}
+
+	fn text(&mut self, text: &str) {
+		let text = decode_entities(text);
+
+		if let Some((_, _, verbatim)) = &mut self.verbatim {
+			verbatim.push_str(&text);
+			return;
+		}
+
+		if !self.line_breaks { return self.inline_text(&text); }
+
+		// * A blank line separates paragraphs, as in plain text.
+		for (index, line) in text.split('\n').enumerate() {
+			if index > 0 {
+				match self.line.ends_with('\n') {
+					true => self.flush(),
+					false => self.line.push('\n')
+				}
+			}
+
+			self.inline_text(line);
+		}
+	}
+
+	/// Appends text to the current line, collapsing whitespace as HTML does.
+	fn inline_text(&mut self, text: &str) {
+		let line_start = self.line.trim().is_empty() || self.line.ends_with('\n');
+
+		let mut collapsed = text.split_whitespace().join(" ");
+		if collapsed.is_empty() && !text.is_empty() { collapsed.push(' '); }
+
+		if text.starts_with(char::is_whitespace) && !collapsed.starts_with(' ') && !line_start { collapsed.insert(0, ' '); }
+		if text.ends_with(char::is_whitespace) && !collapsed.ends_with(' ') { collapsed.push(' '); }
+
+		// * Whitespace is never doubled, nor written at the start of a line.
+		if line_start || self.line.ends_with(' ') { collapsed = collapsed.trim_start().to_owned(); }
+
+		self.line.push_str(&escape_line(&collapsed, line_start));
+	}
+
+	fn write_verbatim(&mut self, kind: Verbatim, text: &str) {
+		// * Zotero stores math along with its TeX delimiters.
+		let math = || text.trim().trim_matches('$').trim().to_owned();
+
+		match kind {
+			Verbatim::Code => {
+				let text = text.replace('\n', " ");
+				let fence = if text.contains('`') { "``" } else { "`" };
+				let pad = if text.starts_with('`') || text.ends_with('`') { " " } else { "" };
+
+				self.line.push_str(&format!("{fence}{pad}{text}{pad}{fence}"));
+			},
+			Verbatim::Math => self.line.push_str(&format!("${}$", math())),
+			Verbatim::CodeBlock => self.write_block(&format!("```\n{}\n```", text.trim_matches('\n'))),
+			Verbatim::MathBlock => self.write_block(&format!("$$\n{}\n$$", math()))
+		}
+	}
+
+	/// Writes out the block being built, if it has any content.
+	fn flush(&mut self) {
+		let heading = self.heading.take();
+		let line = mem::take(&mut self.line);
+
+		let text = line.lines().map(str::trim).filter(|line| !line.is_empty()).join("\n");
+		if text.is_empty() { return; }
+
+		match heading {
+			Some(level) => self.write_block(&format!("{} {}", "#".repeat((level + self.heading_offset).min(6)), text.replace('\n', " "))),
+			None => self.write_block(&text)
+		}
+	}
+
+	fn write_block(&mut self, text: &str) {
+		let in_list = !self.lists.is_empty();
+		let quote = "> ".repeat(self.quote_depth);
+
+		if !self.out.is_empty() {
+			self.out.push('\n');
+
+			if !(in_list && self.last_in_list) {
+				self.out.push_str("> ".repeat(self.quote_depth.min(self.last_quote_depth)).trim_end());
+				self.out.push('\n');
+			}
+		}
+
+		// * Only the first block of a list item carries its marker; the rest are indented beneath it.
+		let marker = self.marker.take().map(|marker| "\t".repeat(self.lists.len().saturating_sub(1)) + &marker);
+		let continuation = "\t".repeat(self.lists.len());
+
+		for (index, line) in text.lines().enumerate() {
+			if index > 0 { self.out.push('\n'); }
+
+			self.out.push_str(&quote);
+
+			match (index, &marker) {
+				(0, Some(marker)) => self.out.push_str(marker),
+				_ => self.out.push_str(&continuation)
+			}
+
+			self.out.push_str(line);
+		}
+
+		self.last_in_list = in_list;
+		self.last_quote_depth = self.quote_depth;
+	}
+
+	fn finish(mut self) -> String {
+		if let Some((kind, _, text)) = self.verbatim.take() { self.write_verbatim(kind, &text); }
+
+		self.flush();
+		self.out
+	}
+}
+
+fn convert(html: &str, line_breaks: bool, heading_offset: usize) -> String {
+	let mut converter = Converter::new(line_breaks, heading_offset);
+
+	for token in tokenize(html) {
+		match token {
+			Token::Text(text) => converter.text(text),
+			Token::Open(name, attributes) => converter.open(&name, &attributes),
+			Token::Close(name) => converter.close(&name)
+		}
+	}
+
+	converter.finish()
+}
+
+/// Converts a Zotero note into Markdown, moving its headings down by the given number of levels.
+pub fn html_to_markdown(html: &str, heading_offset: usize) -> String {
+	convert(html, false, heading_offset)
+}
+
+/// Converts text that may be either plain or rich text, as annotation comments can be, into Markdown.
+///
+/// Unlike in notes, line breaks within rich text are kept.
+pub fn rich_text_to_markdown(text: &str) -> String {
+	let rich = tokenize(text).iter().any(|token| match token {
+		Token::Open(name, _) | Token::Close(name) => RICH_TEXT_ELEMENTS.contains(&name.as_str()),
+		Token::Text(_) => false
+	});
+
+	match rich {
+		true => convert(text, true, 0),
+		false => escape_markdown(text)
+	}
+}
+
+/// Promotes lines consisting solely of a header name (as commonly used to structure comments) into headings.
+pub fn promote_headers(markdown: &str, rules: &[HeaderRule]) -> String {
+	let names: Vec<(String, &HeaderRule)> = rules.iter().map(|rule| (escape_markdown_inline(&rule.name), rule)).collect();
+
+	markdown.lines().map(|line| match names.iter().find(|(name, _)| line.trim() == name) {
+		Some((name, rule)) => format!("{} {name}", "#".repeat(rule.level.into())),
+		None => line.to_owned()
+	}).join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn convert_notes() {
+		let note = concat!(
+			"<div data-schema-version=\"9\"><h1>Title &amp; more</h1>\n",
+			"<p>Some <strong>bold </strong>and <em>italic</em> text, <a href=\"https://example.com/a b\">a link</a>, <code>x[0]</code> and <span class=\"math\">$x^2$</span>.</p>\n",
+			"<ul><li><p>one</p></li><li><p>two</p><ol start=\"3\"><li>three</li></ol></li></ul>\n",
+			"<blockquote><p>quoted</p><p># not a heading</p></blockquote>\n",
+			"<pre class=\"math\">$$\\sum_i i$$</pre><pre>let a = 1 &lt; 2;\n</pre></div>"
+		);
+
+		assert_eq!(html_to_markdown(note, 1), concat!(
+			"## Title \\& more\n\n",
+			"Some **bold** and *italic* text, [a link](https://example.com/a%20b), `x[0]` and $x^2$.\n\n",
+			"- one\n- two\n\t3. three\n\n",
+			"> quoted\n>\n> \\# not a heading\n\n",
+			"$$\n\\sum_i i\n$$\n\n```\nlet a = 1 < 2;\n```"
+		));
+
+		assert_eq!(html_to_markdown("<ol start=\"4294967295\"><li>a</li><li>b</li></ol>", 0), "4294967295. a\n4294967295. b");
+	}
+
+	#[test]
+	fn convert_comments() {
+		assert_eq!(rich_text_to_markdown("plain <not a tag> #1"), "plain \\<not a tag\\> \\#1");
+		assert_eq!(rich_text_to_markdown("Summary\n<b>bold</b> and <i>it</i>\n\nnext"), "Summary\n**bold** and *it*\n\nnext");
+
+		let rules = [HeaderRule { name: "Summary".to_owned(), level: 2 }];
+		assert_eq!(promote_headers("Summary\nnot Summary\n Summary", &rules), "## Summary\nnot Summary\n## Summary");
+	}
+}
\ No newline at end of file
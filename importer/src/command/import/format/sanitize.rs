@@ -0,0 +1,92 @@
+use std::borrow::Cow;
+
+use itertools::Itertools;
+
+use crate::global::{PERSISTENT_BEGIN, PERSISTENT_BEGIN_NAMED, PERSISTENT_END};
+
+/// Characters given meaning by (Obsidian flavoured) Markdown or HTML wherever they appear within a line.
+const SPECIAL_CHARS: &[char] = &['\\', '`', '*', '_', '[', ']', '#', '|', '~', '=', '$', '%', '^', '<', '>', '&'];
+
+/// Characters that cannot be represented within a wikilink, even when escaped.
+const WIKILINK_CHARS: &[char] = &['[', ']', '|'];
+
+/// Escapes text taken from Zotero so that it is displayed literally within a Markdown block, keeping its line structure.
+///
+/// Leading indentation is dropped, as it would otherwise turn lines into code blocks.
+pub fn escape_markdown(text: &str) -> String {
+	text.lines().map(|line| escape_line(line.trim_start(), true)).join("\n")
+}
+
+/// Escapes text for use within a single line of Markdown (such as a heading or link text), joining its lines with spaces.
+pub fn escape_markdown_inline(text: &str) -> String {
+	escape_line(&text.split_whitespace().join(" "), true)
+}
+
+/// Makes text usable as the displayed text of a wikilink, in which the characters delimiting the link cannot be escaped.
+pub fn wikilink_alias(text: &str) -> String {
+	text.split_whitespace().join(" ").replace(WIKILINK_CHARS, " ").split_whitespace().join(" ")
+}
+
+/// Escapes text placed within inline HTML, where Obsidian does not apply Markdown and so only HTML-significant characters need escaping.
+pub fn escape_html(text: &str) -> String {
+	text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Escapes text within a line, also escaping any list marker it starts with if it begins the line.
+pub(super) fn escape_line(line: &str, line_start: bool) -> String {
+	// * List markers are only significant at the start of a line.
+	let marker: Option<usize> = match line.chars().next().filter(|_| line_start) {
+		Some('-' | '+') => Some(0),
+		Some(c) if c.is_ascii_digit() => {
+			let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+			line[digits..].starts_with(['.', ')']).then_some(digits)
+		},
+		_ => None
+	};
+
+	let mut out = String::with_capacity(line.len());
+
+	for (index, c) in line.char_indices() {
+		if Some(index) == marker || SPECIAL_CHARS.contains(&c) { out.push('\\'); }
+		out.push(c);
+	}
+
+	out
+}
+
+/// Breaks up any persistent section delimiters within text, so that text inserted into a note can never be mistaken for a persistent section.
+pub fn neutralize_delimiters(text: &str) -> Cow<'_, str> {
+	let (named_prefix, _) = PERSISTENT_BEGIN_NAMED;
+
+	if ![PERSISTENT_BEGIN, named_prefix, PERSISTENT_END].iter().any(|delimiter| text.contains(delimiter)) { return Cow::Borrowed(text); }
+
+	Cow::Owned(
+		text.replace(PERSISTENT_BEGIN, &PERSISTENT_BEGIN.replacen('<', "&lt;", 1))
+			.replace(named_prefix, &named_prefix.replacen('<', "&lt;", 1))
+			.replace(PERSISTENT_END, &PERSISTENT_END.replace('%', "\\%"))
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::super::scan::persistent::get_persistent_sections;
+
+	#[test]
+	fn escape_text() {
+		assert_eq!(escape_markdown("plain text, (really)."), "plain text, (really).");
+		assert_eq!(escape_markdown("# Title\n  - item\n12. one\n-3"), "\\# Title\n\\- item\n12\\. one\n\\-3");
+		assert_eq!(escape_markdown("[[link]] %%comment%% <b>a & b</b> ==mark=="), "\\[\\[link\\]\\] \\%\\%comment\\%\\% \\<b\\>a \\& b\\</b\\> \\=\\=mark\\=\\=");
+		assert_eq!(escape_markdown_inline("two\n\nlines"), "two lines");
+		assert_eq!(wikilink_alias("a [b] | c"), "a b c");
+		assert_eq!(escape_html("<b>a & b</b>"), "&lt;b&gt;a &amp; b&lt;/b&gt;");
+	}
+
+	#[test]
+	fn neutralize_sections() {
+		let text = "<!--SZO-Persist-Begin-->x%%SZO-Persist-End%% <!--SZO-Persist-Begin:a-->y%%SZO-Persist-End%%";
+
+		assert_eq!(get_persistent_sections(&neutralize_delimiters(text)), Ok(vec![]));
+		assert_eq!(neutralize_delimiters("plain"), Cow::Borrowed("plain"));
+	}
+}
\ No newline at end of file
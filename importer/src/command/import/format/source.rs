@@ -1,99 +1,372 @@
-use std::{fs::File, io::{self, Write}};
+use std::{cmp::Ordering, collections::HashMap};
 
 use serde::Serialize;
 
-use crate::api::import::source::SourceImport;
+use crate::api::{import::{annotation::Annotation, attachment::AttachmentImport, note::NoteImport, source::{Attribution, SourceImport, ANONYMOUS, NO_DATE}}, shared::CreatorRole};
 
-use super::target::NoteTarget;
+use super::{citation::{inline_citation, reference}, rich_text::{html_to_markdown, promote_headers, rich_text_to_markdown}, sanitize::escape_markdown, target::NoteTarget, template::{NoteRenderError, TemplateKind, Templates}};
+use super::super::config::{AnnotationConfig, AnnotationGrouping, CitationStyle, ColourMapping, HeaderRule, SourceField};
+use super::super::scan::persistent::{FetchPersistentError, PersistedSections};
 
 #[derive(Debug, Clone)]
 pub struct SourceImportData<'a> {
-	pub source: &'a SourceImport
+	pub source: &'a SourceImport,
+	pub attachments: &'a [AttachmentImport],
+	/// The unsanitized path of the collection the note is placed in, if any.
+	pub collection: Option<&'a str>,
+	pub notes: &'a [NoteImport],
+	pub headers: &'a [HeaderRule],
+	/// The metadata written to the note's frontmatter.
+	pub fields: &'a [SourceField],
+	pub style: CitationStyle,
+	/// The citation key of the source, as pinned in Zotero or generated.
+	pub citekey: &'a str,
+	/// Annotations to write inline, turning the note into a combined literature note.
+	pub inline: Option<InlineAnnotations<'a>>
 }
 
 #[derive(Debug, Clone)]
-pub struct SourcePersist {
-	pub content_section: String
+pub struct InlineAnnotations<'a> {
+	pub annotations: &'a [Annotation],
+	pub grouping: AnnotationGrouping,
+	/// The link targets of annotation images within the vault, by annotation key.
+	pub images: &'a HashMap<String, String>,
+	pub config: &'a AnnotationConfig
 }
 
-impl Default for SourcePersist {
-	fn default() -> Self {
-		Self {
-			content_section: "\n".to_owned()
-		}
-	}
+#[derive(Debug, Clone, Default)]
+pub struct SourcePersist {
+	pub sections: PersistedSections
 }
 
 pub type SourceTarget<'a> = NoteTarget<'a, SourceImportData<'a>, SourcePersist>;
 
+/// Frontmatter of source notes, where everything but the identifying properties is only written if allowed by the configuration.
 #[derive(Debug, Clone, Serialize)]
 pub struct SourceProperties {
-	pub authors: Vec<String>,
-	pub date: String,
-	pub tags: Vec<String>
+	pub zotero_key: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub item_type: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub title: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub short_title: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub authors: Option<Vec<String>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub attributed_to: Option<Vec<String>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub editors: Option<Vec<String>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub series_editors: Option<Vec<String>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub translators: Option<Vec<String>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub book_authors: Option<Vec<String>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub reviewed_authors: Option<Vec<String>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub contributors: Option<Vec<String>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub date: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub publication: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub volume: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub issue: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub pages: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub edition: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub series: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub publisher: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub place: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub doi: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub isbn: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub issn: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub url: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub language: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub citation_key: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub date_added: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub date_modified: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub citation: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub tags: Option<Vec<String>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub zotero_collection: Option<String>
 }
 
-#[derive(Debug)]
-pub enum SourceExportError {
-	Io(io::Error),
-	PropertyDeserialize(serde_yml::Error)
-}
+impl SourceProperties {
+	fn new(source: &SourceImport, collection: Option<&str>, fields: &[SourceField], citation: &str, citekey: &str) -> Self {
+		let SourceImport { metadata, .. } = source;
+
+		// * Creator lists are written even when empty, whereas unset metadata is left out entirely.
+		let allowed = |field: SourceField| fields.contains(&field);
+		let names = |field: SourceField, roles: &[CreatorRole]| allowed(field).then(|| {
+			source.creators.iter().filter(|creator| roles.contains(&creator.role)).map(|creator| creator.to_string()).collect()
+		});
+		let text = |field: SourceField, value: &Option<String>| value.clone().filter(|_| allowed(field));
 
-impl From<io::Error> for SourceExportError {
-	fn from(value: io::Error) -> Self {
-		Self::Io(value)
+		Self {
+			zotero_key: source.key.clone(),
+			item_type: text(SourceField::ItemType, &Some(source.kind.clone())),
+			title: text(SourceField::Title, &metadata.title),
+			short_title: text(SourceField::ShortTitle, &metadata.short_title),
+			authors: names(SourceField::Authors, &[CreatorRole::Author]),
+			// * Only documents without authors are attributed to anyone else.
+			attributed_to: match source.attribution() {
+				(Attribution::Authors, _) => None,
+				(Attribution::Anonymous, _) => Some(vec![ANONYMOUS.to_owned()]),
+				(_, creators) => Some(creators.iter().map(|creator| creator.to_string()).collect())
+			}.filter(|_| allowed(SourceField::AttributedTo)),
+			editors: names(SourceField::Editors, &[CreatorRole::Editor]),
+			series_editors: names(SourceField::SeriesEditors, &[CreatorRole::SeriesEditor]),
+			translators: names(SourceField::Translators, &[CreatorRole::Translator]),
+			book_authors: names(SourceField::BookAuthors, &[CreatorRole::BookAuthor]),
+			reviewed_authors: names(SourceField::ReviewedAuthors, &[CreatorRole::ReviewedAuthor]),
+			contributors: names(SourceField::Contributors, &[CreatorRole::Contributor, CreatorRole::Other]),
+			date: text(SourceField::Date, &Some(match source.date.trim() {
+				"" => NO_DATE.to_owned(),
+				date => date.to_owned()
+			})),
+			publication: text(SourceField::Publication, &metadata.publication),
+			volume: text(SourceField::Volume, &metadata.volume),
+			issue: text(SourceField::Issue, &metadata.issue),
+			pages: text(SourceField::Pages, &metadata.pages),
+			edition: text(SourceField::Edition, &metadata.edition),
+			series: text(SourceField::Series, &metadata.series),
+			publisher: text(SourceField::Publisher, &metadata.publisher),
+			place: text(SourceField::Place, &metadata.place),
+			doi: text(SourceField::Doi, &metadata.doi),
+			isbn: text(SourceField::Isbn, &metadata.isbn),
+			issn: text(SourceField::Issn, &metadata.issn),
+			url: text(SourceField::Url, &source.url),
+			language: text(SourceField::Language, &metadata.language),
+			citation_key: text(SourceField::CitationKey, &Some(citekey.to_owned())),
+			date_added: text(SourceField::DateAdded, &Some(source.date_added.clone())),
+			date_modified: text(SourceField::DateModified, &Some(source.date_modified.clone())),
+			citation: text(SourceField::Citation, &Some(citation.to_owned())),
+			tags: allowed(SourceField::Tags).then(|| source.tags.iter().map(|s| { s.name.replace(" ", "_") }).collect()),
+			zotero_collection: collection.map(|s| s.to_owned())
+		}
 	}
 }
 
+#[derive(Debug)]
+pub enum SourceExportError {
+	PropertyDeserialize(serde_yml::Error),
+	Template(minijinja::Error),
+	Persistent(FetchPersistentError)
+}
+
 impl From<serde_yml::Error> for SourceExportError {
 	fn from(value: serde_yml::Error) -> Self {
 		Self::PropertyDeserialize(value)
 	}
 }
 
-pub fn write_source(target: SourceTarget) -> Result<(), SourceExportError> {
-	let SourceTarget { file, data, persist } = target;
-	let SourceImportData { source } = data;
+impl From<NoteRenderError> for SourceExportError {
+	fn from(value: NoteRenderError) -> Self {
+		match value {
+			NoteRenderError::Template(error) => Self::Template(error),
+			NoteRenderError::Persistent(error) => Self::Persistent(error)
+		}
+	}
+}
 
-	let props = SourceProperties {
-		authors: source.authors.iter().map(|a| a.to_string()).collect(),
-		date: source.date.clone(),
-		tags: source.tags.iter().map(|s| { s.name.replace(" ", "_") }).collect()
-	};
+pub fn write_source(target: SourceTarget, templates: &Templates) -> Result<(), SourceExportError> {
+	let SourceTarget { out, data, persist } = target;
+	let SourceImportData { source, attachments, collection, notes, headers, fields, style, citekey, inline } = data;
+
+	let citation = reference(source, style);
+	let props = SourceProperties::new(source, collection, fields, &citation.to_plain(), citekey);
 
-	let SourcePersist { content_section: persist_sec } = persist.unwrap_or_default();
+	let SourcePersist { sections } = persist.unwrap_or_default();
 	
-	SourceNote {
+	let kind = match inline {
+		Some(_) => TemplateKind::Literature,
+		None => TemplateKind::Source
+	};
+
+	let note = templates.render_note(kind, SourceNote {
 		properties: &serde_yml::to_string(&props)?,
-		key: &source.key,
-		persist: &persist_sec,
-		title: &source.title,
-		content: source.note.as_ref().map(|s| s.as_str())
-	}.write_to(file)?;
+		source,
+		attachments,
+		collection,
+		// * Notes are placed under a heading of their own, so their headings are moved down a level.
+		notes: notes.iter().map(|note| ChildNote { note, content: html_to_markdown(&note.content, 1) }).collect(),
+		groups: inline.map(|inline| group_annotations(&inline, source, attachments, headers, style)),
+		citation: &citation.to_markdown(),
+		citekey,
+		persistent: &sections,
+		content: &escape_markdown(source.note.as_deref().unwrap_or_default())
+	}, &sections)?;
+
+	out.push_str(&note);
 
 	Ok(())
 }
 
+/// Context made available to source note templates.
+#[derive(Serialize)]
 struct SourceNote<'a> {
 	properties: &'a str,
-	key: &'a str,
-	persist: &'a str,
-	title: &'a str,
-	content: Option<&'a str>
+	source: &'a SourceImport,
+	attachments: &'a [AttachmentImport],
+	collection: Option<&'a str>,
+	notes: Vec<ChildNote<'a>>,
+	/// The full reference to the document, as Markdown.
+	citation: &'a str,
+	citekey: &'a str,
+	/// The inline annotations of a literature note.
+	groups: Option<Vec<AnnotationGroup<'a>>>,
+	persistent: &'a PersistedSections,
+	content: &'a str
+}
+
+/// Context made available to source note templates for each child note.
+#[derive(Debug, Serialize)]
+struct ChildNote<'a> {
+	#[serde(flatten)]
+	note: &'a NoteImport,
+	content: String
+}
+
+/// A heading within a literature note, along with the annotations placed under it.
+#[derive(Debug, Serialize)]
+struct AnnotationGroup<'a> {
+	label: String,
+	annotations: Vec<InlineAnnotation<'a>>
 }
 
-impl<'a> SourceNote<'a> {
-	pub fn write_to(self, out: &mut File) -> Result<(), io::Error> {
-		let Self {
-			properties,
-			key,
-			persist,
-			title,
-			content
-		} = self;
+/// Context made available to literature note templates for each annotation.
+#[derive(Debug, Serialize)]
+struct InlineAnnotation<'a> {
+	annotation: &'a Annotation,
+	link: String,
+	citation: String,
+	colour: Option<&'a ColourMapping>,
+	callout: Option<&'a str>,
+	image: Option<&'a str>,
+	content: String
+}
+
+/// Sorts annotations into reading order (by attachment, page, and then from the top of the page down) and groups them.
+///
+/// Colour groups are ordered by the first annotation of each colour, and are named by their colour mapping if they have one.
+fn group_annotations<'a>(inline: &InlineAnnotations<'a>, source: &SourceImport, attachments: &[AttachmentImport], headers: &[HeaderRule], style: CitationStyle) -> Vec<AnnotationGroup<'a>> {
+	let attachment_index = |annotation: &Annotation| attachments.iter().position(|attachment| attachment.key == annotation.attachment).unwrap_or(usize::MAX);
+
+	let mut annotations: Vec<&Annotation> = inline.annotations.iter().collect();
+
+	annotations.sort_by(|a, b| {
+		attachment_index(a).cmp(&attachment_index(b))
+			.then(a.position.page_index.cmp(&b.position.page_index))
+			.then(b.position.top().partial_cmp(&a.position.top()).unwrap_or(Ordering::Equal))
+	});
+
+	let label = |annotation: &Annotation| match inline.grouping {
+		AnnotationGrouping::Page => match attachments.iter().find(|attachment| attachment.key == annotation.attachment) {
+			Some(attachment) if attachments.len() > 1 => format!("{}, page {}", attachment.title, annotation.position.page_label),
+			_ => format!("Page {}", annotation.position.page_label)
+		},
+		AnnotationGrouping::Colour => match inline.config.colour_mapping(&annotation.colour) {
+			Some(mapping) => mapping.label.clone(),
+			None => annotation.colour.to_string()
+		}
+	};
+
+	let mut groups: Vec<AnnotationGroup> = Vec::new();
+
+	for annotation in annotations {
+		let label = label(annotation);
+
+		let entry = InlineAnnotation {
+			annotation,
+			link: annotation.open_link(),
+			citation: inline_citation(source, style, Some(&annotation.position.page_label)),
+			colour: inline.config.colour_mapping(&annotation.colour),
+			callout: inline.config.callout(&annotation.colour),
+			image: inline.images.get(&annotation.key).map(|image| image.as_str()),
+			content: promote_headers(&rich_text_to_markdown(annotation.comment.as_deref().unwrap_or_default()), headers)
+		};
+
+		match groups.iter_mut().find(|group| group.label == label) {
+			Some(group) => group.annotations.push(entry),
+			None => groups.push(AnnotationGroup { label, annotations: vec![entry] })
+		}
+	}
+
+	groups
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn source() -> SourceImport {
+		SourceImport::fixture().with_kind("book")
+			.with_institution(CreatorRole::Author, "A").with_institution(CreatorRole::Editor, "E").with_institution(CreatorRole::Other, "C")
+			.with_metadata(serde_json::json!({ "doi": "10.1/x", "volume": "3" }))
+	}
+
+	fn annotation(key: &str, page: u32, top: f64, colour: &str) -> Annotation {
+		serde_json::from_value(serde_json::json!({
+			"key": key,
+			"kind": "highlight",
+			"position": { "page_index": page, "page_label": (page + 1).to_string(), "rects": [[0.0, top - 10.0, 100.0, top]] },
+			"colour": colour,
+			"date_added": "2024-01-01T00:00:00Z",
+			"date_modified": "2024-01-01T00:00:00Z",
+			"tags": []
+		})).unwrap()
+	}
+
+	#[test]
+	fn group_in_reading_order() {
+		let annotations = [annotation("C", 1, 500.0, "#ff0000"), annotation("B", 0, 100.0, "#ff0000"), annotation("A", 0, 700.0, "#00ff00")];
+		let images = HashMap::new();
+		let config = AnnotationConfig::default();
+
+		let keys = |grouping| -> Vec<(String, Vec<String>)> {
+			group_annotations(&InlineAnnotations { annotations: &annotations, grouping, images: &images, config: &config }, &source(), &[], &[], CitationStyle::Apa).into_iter()
+				.map(|group| (group.label, group.annotations.iter().map(|entry| entry.annotation.key.clone()).collect()))
+				.collect()
+		};
+
+		assert_eq!(keys(AnnotationGrouping::Page), vec![
+			("Page 1".to_owned(), vec!["A".to_owned(), "B".to_owned()]),
+			("Page 2".to_owned(), vec!["C".to_owned()])
+		]);
+
+		assert_eq!(keys(AnnotationGrouping::Colour), vec![
+			("#00ff00".to_owned(), vec!["A".to_owned()]),
+			("#ff0000".to_owned(), vec!["B".to_owned(), "C".to_owned()])
+		]);
+	}
+
+	#[test]
+	fn filter_properties() {
+		let props = SourceProperties::new(&source(), None, &[SourceField::Editors, SourceField::Contributors, SourceField::Doi, SourceField::Publisher, SourceField::CitationKey], "", "a2020");
+
+		assert_eq!(serde_yml::to_string(&props).unwrap(), "zotero_key: KEY\neditors:\n- E\ncontributors:\n- C\ndoi: '10.1/x'\ncitation_key: a2020\n");
 
-		let content = content.unwrap_or_default();
+		let edited = SourceImport { creators: source().creators.into_iter().filter(|creator| creator.role != CreatorRole::Author).collect(), ..source() };
+		let props = SourceProperties::new(&edited, None, &[SourceField::Authors, SourceField::AttributedTo, SourceField::Editors], "", "");
 
-		out.write_all(format!("---\n{properties}---\n\n[Open in Zotero](zotero://select/library/items/{key})\n\n**Persistent Notes**\n\n---\n\n<!--SZO-Persist-Begin-->{persist}%%SZO-Persist-End%%\n\n# {title}\n\n---\n\n{content}").as_bytes())
+		assert_eq!(serde_yml::to_string(&props).unwrap(), "zotero_key: KEY\nauthors: []\nattributed_to:\n- E\neditors:\n- E\n");
 	}
 }
\ No newline at end of file
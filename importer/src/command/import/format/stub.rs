@@ -0,0 +1,65 @@
+use serde::Serialize;
+
+use crate::api::import::source::SourceImport;
+
+use super::{target::NoteTarget, template::{TemplateKind, Templates}};
+
+#[derive(Debug, Clone)]
+pub struct StubImportData<'a> {
+	pub source: &'a SourceImport,
+	/// The link target of the source note.
+	pub target: &'a str
+}
+
+/// Link stubs have no persistent sections, as they are entirely regenerated on every import.
+pub type StubTarget<'a> = NoteTarget<'a, StubImportData<'a>, ()>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StubProperties {
+	pub zotero_stub: String
+}
+
+#[derive(Debug)]
+pub enum StubExportError {
+	PropertyDeserialize(serde_yml::Error),
+	Template(minijinja::Error)
+}
+
+impl From<serde_yml::Error> for StubExportError {
+	fn from(value: serde_yml::Error) -> Self {
+		Self::PropertyDeserialize(value)
+	}
+}
+
+impl From<minijinja::Error> for StubExportError {
+	fn from(value: minijinja::Error) -> Self {
+		Self::Template(value)
+	}
+}
+
+pub fn write_stub(target: StubTarget, templates: &Templates) -> Result<(), StubExportError> {
+	let StubTarget { out, data, .. } = target;
+	let StubImportData { source, target } = data;
+
+	let props = StubProperties {
+		zotero_stub: source.key.clone()
+	};
+
+	let note = templates.render(TemplateKind::Stub, StubNote {
+		properties: &serde_yml::to_string(&props)?,
+		source,
+		target
+	})?;
+
+	out.push_str(&note);
+
+	Ok(())
+}
+
+/// Context made available to link stub templates.
+#[derive(Serialize)]
+struct StubNote<'a> {
+	properties: &'a str,
+	source: &'a SourceImport,
+	target: &'a str
+}
\ No newline at end of file
@@ -1,9 +1,7 @@
-use std::fs::File;
-
 #[derive(Debug)]
 pub struct NoteTarget<'a, Data, Persist> {
-	/// The file to write the data to.
-	pub file: &'a mut File,
+	/// The buffer to write the note to.
+	pub out: &'a mut String,
 	/// All relevent import data.
 	pub data: Data,
 	/// Persisted data from the previous contents, if a matching file was located.
@@ -15,6 +13,6 @@ pub trait NTarget {
 	type Persist;
 	type Error;
 
-	fn new(file: &mut File, data: Self::Data, persist: Option<Self::Persist>) -> Self;
+	fn new(out: &mut String, data: Self::Data, persist: Option<Self::Persist>) -> Self;
 	fn write(&mut self) -> Result<(), Self::Error>;
 }
\ No newline at end of file
@@ -0,0 +1,216 @@
+use std::{fs, io, path::{Path, PathBuf}};
+
+use minijinja::{escape_formatter, Environment, ErrorKind, Output, State, Value};
+use serde::Serialize;
+
+use crate::api::import::annotation::AnnotationType;
+
+use super::sanitize::{escape_html, escape_markdown, escape_markdown_inline, neutralize_delimiters, wikilink_alias};
+use super::super::{config::TemplateConfig, scan::persistent::{format_persistent_section, get_persistent_sections, is_section_name, FetchPersistentError, PersistedSections}};
+
+static DEFAULT_SOURCE: &str = include_str!("templates/source.md");
+static DEFAULT_ANNOTATION: &str = include_str!("templates/annotation.md");
+static DEFAULT_ANNOTATION_HIGHLIGHT: &str = include_str!("templates/annotation/highlight.md");
+static DEFAULT_ANNOTATION_UNDERLINE: &str = include_str!("templates/annotation/underline.md");
+static DEFAULT_ANNOTATION_NOTE: &str = include_str!("templates/annotation/note.md");
+static DEFAULT_ANNOTATION_TEXT: &str = include_str!("templates/annotation/text.md");
+static DEFAULT_ANNOTATION_IMAGE: &str = include_str!("templates/annotation/image.md");
+static DEFAULT_ANNOTATION_INK: &str = include_str!("templates/annotation/ink.md");
+static DEFAULT_ANNOTATION_UNKNOWN: &str = include_str!("templates/annotation/unknown.md");
+static DEFAULT_STUB: &str = include_str!("templates/stub.md");
+static DEFAULT_LITERATURE: &str = include_str!("templates/literature.md");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateKind {
+	Source,
+	Annotation(AnnotationType),
+	Stub,
+	Literature
+}
+
+/// The name of the shared annotation layout, which the per-type annotation templates extend.
+const ANNOTATION_BASE: &str = "annotation";
+
+impl TemplateKind {
+	const fn name(self) -> &'static str {
+		match self {
+			TemplateKind::Source => "source",
+			TemplateKind::Annotation(kind) => match kind {
+				AnnotationType::Highlight => "annotation/highlight",
+				AnnotationType::Underline => "annotation/underline",
+				AnnotationType::Note => "annotation/note",
+				AnnotationType::Text => "annotation/text",
+				AnnotationType::Image => "annotation/image",
+				AnnotationType::Ink => "annotation/ink",
+				AnnotationType::Unknown => "annotation/unknown"
+			},
+			TemplateKind::Stub => "stub",
+			TemplateKind::Literature => "literature"
+		}
+	}
+}
+
+const fn default_annotation_template(kind: AnnotationType) -> &'static str {
+	match kind {
+		AnnotationType::Highlight => DEFAULT_ANNOTATION_HIGHLIGHT,
+		AnnotationType::Underline => DEFAULT_ANNOTATION_UNDERLINE,
+		AnnotationType::Note => DEFAULT_ANNOTATION_NOTE,
+		AnnotationType::Text => DEFAULT_ANNOTATION_TEXT,
+		AnnotationType::Image => DEFAULT_ANNOTATION_IMAGE,
+		AnnotationType::Ink => DEFAULT_ANNOTATION_INK,
+		AnnotationType::Unknown => DEFAULT_ANNOTATION_UNKNOWN
+	}
+}
+
+#[derive(Debug)]
+pub enum TemplateLoadError {
+	Io(PathBuf, io::Error),
+	Syntax(minijinja::Error)
+}
+
+impl From<minijinja::Error> for TemplateLoadError {
+	fn from(value: minijinja::Error) -> Self {
+		Self::Syntax(value)
+	}
+}
+
+/// The set of note templates used for a single import.
+#[derive(Debug)]
+pub struct Templates {
+	env: Environment<'static>
+}
+
+impl Templates {
+	pub fn load(config: &TemplateConfig) -> Result<Self, TemplateLoadError> {
+		let mut env = Environment::new();
+		env.add_function("persist", persist);
+		env.add_filter("quote", quote);
+		env.add_filter("md", |value: String| escape_markdown(&value));
+		env.add_filter("md_inline", |value: String| escape_markdown_inline(&value));
+		env.add_filter("alias", |value: String| wikilink_alias(&value));
+		env.add_filter("html", |value: String| escape_html(&value));
+		env.set_formatter(format_value);
+
+		add_template(&mut env, TemplateKind::Source.name(), config.source.as_deref(), DEFAULT_SOURCE)?;
+		add_template(&mut env, TemplateKind::Stub.name(), config.stub.as_deref(), DEFAULT_STUB)?;
+		add_template(&mut env, TemplateKind::Literature.name(), config.literature.as_deref(), DEFAULT_LITERATURE)?;
+
+		// * The built-in per-type templates extend the (possibly user-provided) base layout, only replacing its `body` block.
+		add_template(&mut env, ANNOTATION_BASE, config.annotation.as_deref(), DEFAULT_ANNOTATION)?;
+
+		for kind in AnnotationType::ALL {
+			let path = config.annotation_types.get(&kind).map(|path| path.as_path());
+
+			add_template(&mut env, TemplateKind::Annotation(kind).name(), path, default_annotation_template(kind))?;
+		}
+
+		Ok(Self { env })
+	}
+
+	pub fn render<C: Serialize>(&self, kind: TemplateKind, context: C) -> Result<String, minijinja::Error> {
+		self.env.get_template(kind.name())?.render(context)
+	}
+
+	/// Renders a note, appending any persisted sections that the template did not place so that their content is never lost.
+	pub fn render_note<C: Serialize>(&self, kind: TemplateKind, context: C, persisted: &PersistedSections) -> Result<String, NoteRenderError> {
+		let mut note = self.render(kind, context)?;
+
+		let placed: Vec<Option<String>> = get_persistent_sections(&note)?.into_iter().map(|section| section.name.map(|s| s.to_owned())).collect();
+
+		for section in persisted.iter().filter(|section| !placed.iter().any(|name| name.as_deref() == section.name)) {
+			note.push_str("\n\n");
+			note.push_str(&format_persistent_section(section.name, section.content));
+		}
+
+		Ok(note)
+	}
+}
+
+#[derive(Debug)]
+pub enum NoteRenderError {
+	Template(minijinja::Error),
+	/// The rendered note contained malformed persistent sections.
+	Persistent(FetchPersistentError)
+}
+
+impl From<minijinja::Error> for NoteRenderError {
+	fn from(value: minijinja::Error) -> Self {
+		Self::Template(value)
+	}
+}
+
+impl From<FetchPersistentError> for NoteRenderError {
+	fn from(value: FetchPersistentError) -> Self {
+		Self::Persistent(value)
+	}
+}
+
+fn add_template(env: &mut Environment<'static>, name: &'static str, path: Option<&Path>, default: &'static str) -> Result<(), TemplateLoadError> {
+	match path {
+		Some(path) => {
+			let source = fs::read_to_string(path).map_err(|e| TemplateLoadError::Io(path.to_owned(), e))?;
+			env.add_template_owned(name, source)?;
+		},
+		None => env.add_template(name, default)?
+	}
+
+	Ok(())
+}
+
+/// Template function emitting a persistent section, filled with the content carried over from the previous note (if any).
+/// 
+/// Called without arguments for the default section, or with a name for a named section.
+fn persist(state: &State, name: Option<String>) -> Result<Value, minijinja::Error> {
+	if let Some(name) = &name {
+		if !is_section_name(name) {
+			return Err(minijinja::Error::new(ErrorKind::InvalidOperation, format!("'{name}' is not a valid persistent section name")));
+		}
+	}
+
+	let content = state.lookup("persistent")
+		.and_then(|sections| sections.get_item(&Value::from(name.as_deref().unwrap_or_default())).ok())
+		.filter(|content| !content.is_undefined())
+		.map(|content| content.to_string())
+		.unwrap_or_else(|| "\n".to_owned());
+
+	Ok(Value::from_safe_string(format_persistent_section(name.as_deref(), &content)))
+}
+
+/// Writes values into rendered templates, neutralizing any persistent section delimiters so that only `persist` can create sections.
+fn format_value(out: &mut Output, state: &State, value: &Value) -> Result<(), minijinja::Error> {
+	match value.as_str() {
+		Some(text) if !value.is_safe() => Ok(out.write_str(&neutralize_delimiters(text))?),
+		_ => escape_formatter(out, state, value)
+	}
+}
+
+/// Template filter prefixing every line with `> `, so that multi-paragraph text stays within a blockquote or callout.
+///
+/// Blank lines at the start and end are dropped, while runs of them in between become a single empty quoted line (separating paragraphs).
+fn quote(value: String) -> String {
+	let mut out: Vec<String> = Vec::new();
+
+	for line in value.lines() {
+		match line.trim().is_empty() {
+			true if out.last().is_none_or(|last| last == ">") => (),
+			true => out.push(">".to_owned()),
+			false => out.push(format!("> {line}"))
+		}
+	}
+
+	if out.last().is_some_and(|last| last == ">") { out.pop(); }
+
+	out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn quote_lines() {
+		assert_eq!(quote("one\ntwo".to_owned()), "> one\n> two");
+		assert_eq!(quote("\n\none\n\n\n  \ntwo\n\n".to_owned()), "> one\n>\n> two");
+		assert_eq!(quote("\n".to_owned()), "");
+	}
+}
\ No newline at end of file
@@ -1,5 +1,10 @@
 mod core;
 pub use core::*;
 
-mod scan;
-mod format;
\ No newline at end of file
+mod config;
+pub use config::*;
+
+pub(crate) mod scan;
+mod format;
+mod naming;
+mod attachment;
\ No newline at end of file
@@ -0,0 +1,329 @@
+use std::{collections::HashMap, fmt::{self, Display}};
+
+use itertools::Itertools;
+use serde::Deserialize;
+
+use crate::{api::import::{annotation::Annotation, source::SourceImport}, util::path::{append_to_segment, sanitize_path_segment}};
+
+/// A note naming pattern, made up of literal text and `{placeholder}` or `{placeholder:argument}` segments.
+///
+/// Braces may be escaped by doubling them (`{{` and `}}`).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct NamePattern {
+	segments: Box<[Segment]>
+}
+
+/// A naming pattern for source notes, which rejects placeholders that only have a meaning for annotations.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct SourcePattern(NamePattern);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+	Literal(String),
+	Placeholder(Placeholder)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placeholder {
+	/// The source title.
+	Title,
+	/// The primary author's last name followed by the publication year, as written by `author_last` and `year`.
+	///
+	/// As names cannot end in a dot, undated documents are named "Smith n.d" rather than "Smith n.d.".
+	ShortName,
+	/// The primary author's last name, falling back to the first editor or institutional creator, or "Anonymous".
+	AuthorLast,
+	/// The publication year, or "n.d." for undated documents (without its final dot if it ends the name).
+	Year,
+	/// The source's citation key, as pinned in Zotero or generated.
+	CiteKey,
+	/// The Zotero key of the item being named.
+	Key,
+	/// The Zotero key of the source item.
+	SourceKey,
+	/// The label of the page an annotation was made on.
+	Page,
+	/// The first `n` words of an annotation's text, or the source title.
+	FirstWords(usize)
+}
+
+impl Display for Placeholder {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Placeholder::Title => write!(f, "{{title}}"),
+			Placeholder::ShortName => write!(f, "{{short_name}}"),
+			Placeholder::AuthorLast => write!(f, "{{author_last}}"),
+			Placeholder::Year => write!(f, "{{year}}"),
+			Placeholder::CiteKey => write!(f, "{{citekey}}"),
+			Placeholder::Key => write!(f, "{{key}}"),
+			Placeholder::SourceKey => write!(f, "{{source_key}}"),
+			Placeholder::Page => write!(f, "{{page}}"),
+			Placeholder::FirstWords(count) => write!(f, "{{first_words:{count}}}")
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternParseError {
+	UnclosedPlaceholder,
+	UnmatchedBrace,
+	UnknownPlaceholder(String),
+	InvalidArgument(String),
+	/// The placeholder only has a meaning for annotation notes, but names source notes.
+	UnsupportedPlaceholder(Placeholder)
+}
+
+impl Display for PatternParseError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			PatternParseError::UnclosedPlaceholder => write!(f, "placeholder is missing a closing '}}'"),
+			PatternParseError::UnmatchedBrace => write!(f, "unmatched '}}' (use '}}}}' for a literal brace)"),
+			PatternParseError::UnknownPlaceholder(name) => write!(f, "unknown placeholder '{name}'"),
+			PatternParseError::InvalidArgument(name) => write!(f, "invalid argument for placeholder '{name}'"),
+			PatternParseError::UnsupportedPlaceholder(placeholder) => write!(f, "the placeholder '{placeholder}' cannot be used to name source notes")
+		}
+	}
+}
+
+impl Placeholder {
+	fn parse(input: &str) -> Result<Self, PatternParseError> {
+		let (name, argument) = match input.split_once(':') {
+			Some((name, argument)) => (name.trim(), Some(argument.trim())),
+			None => (input.trim(), None)
+		};
+
+		let no_argument = |placeholder: Placeholder| match argument {
+			Some(_) => Err(PatternParseError::InvalidArgument(name.to_owned())),
+			None => Ok(placeholder)
+		};
+
+		match name {
+			"title" => no_argument(Placeholder::Title),
+			"short_name" => no_argument(Placeholder::ShortName),
+			"author_last" => no_argument(Placeholder::AuthorLast),
+			"year" => no_argument(Placeholder::Year),
+			"citekey" => no_argument(Placeholder::CiteKey),
+			"key" => no_argument(Placeholder::Key),
+			"source_key" => no_argument(Placeholder::SourceKey),
+			"page" => no_argument(Placeholder::Page),
+			"first_words" => argument.and_then(|arg| arg.parse().ok()).filter(|count| *count > 0)
+				.map(Placeholder::FirstWords)
+				.ok_or_else(|| PatternParseError::InvalidArgument(name.to_owned())),
+			_ => Err(PatternParseError::UnknownPlaceholder(name.to_owned()))
+		}
+	}
+}
+
+impl NamePattern {
+	pub fn parse(input: &str) -> Result<Self, PatternParseError> {
+		let mut segments: Vec<Segment> = Vec::new();
+		let mut literal = String::new();
+		let mut chars = input.chars().peekable();
+
+		while let Some(c) = chars.next() {
+			match c {
+				'{' if chars.next_if_eq(&'{').is_some() => literal.push('{'),
+				'}' if chars.next_if_eq(&'}').is_some() => literal.push('}'),
+				'{' => {
+					let mut inner = String::new();
+
+					loop {
+						match chars.next() {
+							Some('}') => break,
+							Some(c) => inner.push(c),
+							None => return Err(PatternParseError::UnclosedPlaceholder)
+						}
+					}
+
+					if !literal.is_empty() { segments.push(Segment::Literal(std::mem::take(&mut literal))); }
+					segments.push(Segment::Placeholder(Placeholder::parse(&inner)?));
+				},
+				'}' => return Err(PatternParseError::UnmatchedBrace),
+				c => literal.push(c)
+			}
+		}
+
+		if !literal.is_empty() { segments.push(Segment::Literal(literal)); }
+
+		Ok(Self { segments: segments.into_boxed_slice() })
+	}
+
+	/// Renders the pattern, resolving each placeholder through `lookup`.
+	///
+	/// The result is not sanitized.
+	pub fn render<F: Fn(Placeholder) -> Option<String>>(&self, lookup: F) -> Result<String, NamingError> {
+		self.segments.iter().map(|segment| match segment {
+			Segment::Literal(literal) => Ok(literal.clone()),
+			Segment::Placeholder(placeholder) => lookup(*placeholder).ok_or(NamingError::UnsupportedPlaceholder(*placeholder))
+		}).collect()
+	}
+}
+
+impl TryFrom<String> for NamePattern {
+	type Error = PatternParseError;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		Self::parse(&value)
+	}
+}
+
+impl SourcePattern {
+	pub fn parse(input: &str) -> Result<Self, PatternParseError> {
+		let pattern = NamePattern::parse(input)?;
+
+		match pattern.segments.iter().any(|segment| *segment == Segment::Placeholder(Placeholder::Page)) {
+			true => Err(PatternParseError::UnsupportedPlaceholder(Placeholder::Page)),
+			false => Ok(Self(pattern))
+		}
+	}
+}
+
+impl TryFrom<String> for SourcePattern {
+	type Error = PatternParseError;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		Self::parse(&value)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NamingError {
+	/// The placeholder has no meaning for the kind of note being named.
+	UnsupportedPlaceholder(Placeholder)
+}
+
+fn first_words(text: &str, count: usize) -> String {
+	text.split_whitespace().take(count).join(" ")
+}
+
+/// Determines the (sanitized) file name of a source note.
+pub fn source_note_name(pattern: &SourcePattern, source: &SourceImport, citekey: &str) -> Result<String, NamingError> {
+	pattern.0.render(|placeholder| match placeholder {
+		Placeholder::Title => Some(source.title.clone()),
+		Placeholder::ShortName => Some(source.short_name()),
+		Placeholder::AuthorLast => Some(source.attributed_name()),
+		Placeholder::Year => Some(source.year_label()),
+		Placeholder::CiteKey => Some(citekey.to_owned()),
+		Placeholder::Key | Placeholder::SourceKey => Some(source.key.clone()),
+		Placeholder::Page => None,
+		Placeholder::FirstWords(count) => Some(first_words(&source.title, count))
+	}).map(sanitize_path_segment)
+}
+
+/// Determines the (sanitized) file names of a set of annotation notes.
+///
+/// Names that would collide with one another (ignoring case, as not all filesystems are case sensitive) have the annotation key appended to them.
+/// As keys never change, this keeps names stable across reimports.
+pub fn annotation_note_names<'a, I: IntoIterator<Item = &'a Annotation>>(pattern: &NamePattern, source: &SourceImport, citekey: &str, annotations: I) -> Result<Vec<String>, NamingError> {
+	let annotations: Vec<&Annotation> = annotations.into_iter().collect();
+
+	let mut names: Vec<String> = annotations.iter().map(|annot| {
+		pattern.render(|placeholder| match placeholder {
+			Placeholder::Title => Some(source.title.clone()),
+			Placeholder::ShortName => Some(source.short_name()),
+			Placeholder::AuthorLast => Some(source.attributed_name()),
+			Placeholder::Year => Some(source.year_label()),
+			Placeholder::CiteKey => Some(citekey.to_owned()),
+			Placeholder::Key => Some(annot.key.clone()),
+			Placeholder::SourceKey => Some(source.key.clone()),
+			Placeholder::Page => Some(annot.position.page_label.clone()),
+			Placeholder::FirstWords(count) => Some(first_words(annot.text.as_deref().or(annot.comment.as_deref()).unwrap_or_default(), count))
+		}).map(sanitize_path_segment)
+	}).collect::<Result<_, _>>()?;
+
+	// * A suffixed name may in turn collide with another name, so this repeats until every colliding name has been suffixed.
+	let mut suffixed = vec![false; names.len()];
+
+	loop {
+		let mut occurrences: HashMap<String, usize> = HashMap::new();
+		for name in &names { *occurrences.entry(name.to_lowercase()).or_default() += 1; }
+
+		let mut changed = false;
+
+		for ((name, annot), suffixed) in names.iter_mut().zip(&annotations).zip(&mut suffixed) {
+			if !*suffixed && occurrences[&name.to_lowercase()] > 1 {
+				*name = append_to_segment(name, &annot.key);
+				*suffixed = true;
+				changed = true;
+			}
+		}
+
+		if !changed { return Ok(names); }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_pattern() {
+		assert_eq!(NamePattern::parse("{author_last} {year} p{page} {first_words:6}"), Ok(NamePattern { segments: Box::new([
+			Segment::Placeholder(Placeholder::AuthorLast),
+			Segment::Literal(" ".to_owned()),
+			Segment::Placeholder(Placeholder::Year),
+			Segment::Literal(" p".to_owned()),
+			Segment::Placeholder(Placeholder::Page),
+			Segment::Literal(" ".to_owned()),
+			Segment::Placeholder(Placeholder::FirstWords(6))
+		]) }));
+
+		assert_eq!(NamePattern::parse("{{{key}}}"), Ok(NamePattern { segments: Box::new([
+			Segment::Literal("{".to_owned()),
+			Segment::Placeholder(Placeholder::Key),
+			Segment::Literal("}".to_owned())
+		]) }));
+
+		assert_eq!(NamePattern::parse("{key"), Err(PatternParseError::UnclosedPlaceholder));
+		assert_eq!(NamePattern::parse("key}"), Err(PatternParseError::UnmatchedBrace));
+		assert_eq!(NamePattern::parse("{author}"), Err(PatternParseError::UnknownPlaceholder("author".to_owned())));
+		assert_eq!(NamePattern::parse("{first_words}"), Err(PatternParseError::InvalidArgument("first_words".to_owned())));
+		assert_eq!(NamePattern::parse("{first_words:0}"), Err(PatternParseError::InvalidArgument("first_words".to_owned())));
+		assert_eq!(NamePattern::parse("{year:2}"), Err(PatternParseError::InvalidArgument("year".to_owned())));
+		assert_eq!(SourcePattern::parse("{title} p{page}"), Err(PatternParseError::UnsupportedPlaceholder(Placeholder::Page)));
+	}
+
+	#[test]
+	fn render_pattern() {
+		let pattern = NamePattern::parse("{title} ({year})").unwrap();
+
+		assert_eq!(pattern.render(|placeholder| match placeholder {
+			Placeholder::Title => Some("Title".to_owned()),
+			Placeholder::Year => Some("2020".to_owned()),
+			_ => None
+		}), Ok("Title (2020)".to_owned()));
+
+		assert_eq!(NamePattern::parse("{page}").unwrap().render(|_| None), Err(NamingError::UnsupportedPlaceholder(Placeholder::Page)));
+
+		let undated = SourceImport::fixture().with_date("");
+
+		assert_eq!(source_note_name(&SourcePattern::parse("{short_name}").unwrap(), &undated, ""), Ok("Anonymous n.d".to_owned()));
+		assert_eq!(source_note_name(&SourcePattern::parse("{year} {title}").unwrap(), &undated, ""), Ok("n.d. Title".to_owned()));
+	}
+
+	#[test]
+	fn disambiguate_long_names() {
+		let annotation = |key: &str, text: &str| -> Annotation {
+			serde_json::from_value(serde_json::json!({
+				"key": key, "kind": "highlight", "text": text, "colour": "#ffd400",
+				"position": { "page_index": 0, "page_label": "1", "rects": [] },
+				"date_added": "2024-01-01T00:00:00Z", "date_modified": "2024-01-01T00:00:00Z", "tags": []
+			})).unwrap()
+		};
+
+		let pattern = NamePattern::parse("{first_words:60}").unwrap();
+		let long = "word ".repeat(60);
+
+		let names = annotation_note_names(&pattern, &SourceImport::fixture(), "", &[annotation("AAAA1111", &long), annotation("BBBB2222", &long)]).unwrap();
+
+		assert!(names[0].ends_with(" AAAA1111") && names[1].ends_with(" BBBB2222"));
+		assert!(names.iter().all(|name| name.len() <= crate::util::path::MAX_SEGMENT_LEN));
+
+		// * A suffixed name colliding with another name has that name suffixed as well.
+		let names = annotation_note_names(&pattern, &SourceImport::fixture(), "", &[annotation("K1", "x"), annotation("K2", "x"), annotation("K3", "x K1")]).unwrap();
+
+		assert_eq!(names, ["x K1", "x K2", "x K1 K3"]);
+	}
+}
\ No newline at end of file
@@ -1,2 +1,3 @@
 pub mod notes;
-pub mod persistent;
\ No newline at end of file
+pub mod persistent;
+pub mod vault;
\ No newline at end of file
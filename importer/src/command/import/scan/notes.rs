@@ -1,14 +1,18 @@
-use std::{collections::HashMap, fs, io, path::{Path, PathBuf}, vec};
+use std::{collections::BTreeSet, io, path::{Path, PathBuf}, vec};
 
 use console::style;
 use itertools::{Itertools, ZipEq};
 
 use crate::{global::{ANNOTATIONS_PREFIX, SOURCE_PREFIX}, util::{directory::get_files_ext, iter::BorrowableIterator}};
 
+use super::vault::VaultIndex;
+
 #[derive(Debug, Clone)]
 pub struct AnnotationFile {
 	pub path: PathBuf,
-	pub exists: bool
+	pub exists: bool,
+	/// The current location of the note, if it is being moved to `path`.
+	pub moved_from: Option<PathBuf>
 }
 
 #[derive(Debug, Clone)]
@@ -20,9 +24,9 @@ pub struct SourceFiles<AIter: Iterator, FIter: Iterator<Item = AnnotationFile>>
 
 #[derive(Debug)]
 pub enum NoteFetchError {
-	UnrecognizedSources,
-	Interact(dialoguer::Error),
-	Filesystem(io::Error)
+	Filesystem(io::Error),
+	/// A note generated for another item already exists where a note would be placed.
+	NameTaken(PathBuf)
 }
 
 impl From<io::Error> for NoteFetchError {
@@ -31,66 +35,137 @@ impl From<io::Error> for NoteFetchError {
 	}
 }
 
-impl From<dialoguer::Error> for NoteFetchError {
-	fn from(value: dialoguer::Error) -> Self {
-		Self::Interact(value)
-	}
+/// Lists the notes within a directory, treating a missing directory as empty.
+fn get_notes<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>, io::Error> {
+	if path.as_ref().is_dir() { get_files_ext(path, "md") } else { Ok(Vec::new()) }
 }
 
 type AnnotOutIter = vec::IntoIter<AnnotationFile>;
 
+/// Locates the notes belonging to a source and its annotations.
+///
+/// Existing notes are primarily located through their identifying frontmatter properties anywhere in the vault, allowing them to be freely renamed or moved.
+/// Notes without such properties fall back to being located by file name within the output directory.
+/// This fails if a note generated for another item already has the name, rather than overwriting it.
+///
+/// Any remaining notes in the output directory, along with any notes elsewhere that belong to the source, are returned as residuals.
+/// 
+/// Notes located within `relocate_from` are moved into the output directory, unless a note already exists at their new location.
+/// 
+/// This does not modify the filesystem in any way.
 pub fn get_note_files<
 	'a,
 	P: AsRef<Path>,
 	AIter: BorrowableIterator,
-	F: FnMut(&AIter::Item) -> AName,
-	AName: AsRef<str>
-> (path: P, source_name: &str, annotations: AIter, a_map: F) -> Result<SourceFiles<AIter, AnnotOutIter>, NoteFetchError> {
+	F: FnMut(&AIter::Item) -> (AKey, AName),
+	AKey: AsRef<str>,
+	AName: AsRef<Path>
+> (vault: &VaultIndex, path: P, relocate_from: Option<&Path>, source_key: &str, source_name: &str, annotations: AIter, mut a_map: F) -> Result<SourceFiles<AIter, AnnotOutIter>, NoteFetchError> {
 	let source_path = path.as_ref().join(SOURCE_PREFIX);
 	let annotation_path = path.as_ref().join(ANNOTATIONS_PREFIX);
 
-	fs::create_dir_all(&annotation_path)?;
-	
-	let mut residuals = get_files_ext(&source_path, "md")?;
+	// * Notes in the output directory belonging to other items are left alone.
+	let mut residuals: BTreeSet<PathBuf> = get_notes(&source_path)?.into_iter()
+		.chain(get_notes(&annotation_path)?)
+		.filter(|path| vault.identity(path).is_none_or(|identity| identity.zotero_key.as_deref() == Some(source_key)))
+		.chain(vault.notes_of(source_key).map(|path| path.to_owned()))
+		.collect();
 
-	let source_exists: bool = residuals.iter().position(|elem| elem.file_stem().unwrap() == source_name).map(|index| {
-		residuals.swap_remove(index)
-	}).is_some();
+	// * Fallback to locating a note by name, which must not overwrite a note claimed by another item.
+	let locate_by_name = |path: PathBuf| -> Result<AnnotationFile, NoteFetchError> {
+		if vault.identity(&path).is_some() { return Err(NoteFetchError::NameTaken(path)); }
 
-	let mut out: Vec<AnnotationFile> = annotations.borrowed().map(a_map).map(|s| AnnotationFile { path: s.as_ref().into(), exists: false }).collect();
-	let mut keys: HashMap<&Path, &mut bool> = out.iter_mut().map(|a_file| (a_file.path.as_path(), &mut a_file.exists)).collect();
+		Ok(AnnotationFile { exists: path.is_file(), path, moved_from: None })
+	};
 
-	let mut db = get_files_ext(annotation_path, "md")?;
+	let located = |found: &Path, target: PathBuf| -> AnnotationFile {
+		match relocate_from {
+			Some(dir) if found.starts_with(dir) && !target.exists() => AnnotationFile { path: target, exists: true, moved_from: Some(found.to_owned()) },
+			_ => AnnotationFile { path: found.to_owned(), exists: true, moved_from: None }
+		}
+	};
 
-	// TODO: Use `Vec::extract_if` to avoid the `path.clone()` when it is stabilized (should be May 15 2025 for rustc 1.87).
-	// TODO: Clean this up in general as well if possible. Probably try to get rip of the string conversion.
-	db.retain(|path| {
-		keys.get_mut(Path::new(path.file_stem().unwrap())).map(|result| **result = true ).is_none()
-	});
+	let mut claim = |file: &AnnotationFile| {
+		residuals.remove(&file.path);
+		if let Some(from) = &file.moved_from { residuals.remove(from); }
+	};
 
-	residuals.extend(db);
+	let source_target = source_path.join(format!("{source_name}.md"));
 
-	if !residuals.is_empty() {
-		if !query_delete_files(residuals.iter())? { return Err(NoteFetchError::UnrecognizedSources) }
-	}
+	let source = match vault.find_source(source_key) {
+		Some(path) => located(path, source_target),
+		None => locate_by_name(source_target)?
+	};
+
+	claim(&source);
+
+	let out: Vec<AnnotationFile> = annotations.borrowed().map(|item| {
+		let (key, name) = a_map(item);
+
+		// * Names are relative to the annotations directory, and may place notes in a subfolder of it.
+		let mut target = annotation_path.join(name);
+		target.as_mut_os_string().push(".md");
+
+		let file = match vault.find_annotation(key.as_ref()) {
+			Some(path) => located(path, target),
+			None => locate_by_name(target)?
+		};
+
+		claim(&file);
+		Ok(file)
+	}).collect::<Result<_, NoteFetchError>>()?;
 
 	Ok(SourceFiles {
-		source: AnnotationFile { path: source_path.join(source_name).with_extension("md"), exists: source_exists },
+		source,
 		annotations: annotations.zip_eq(out.into_iter()),
-		residuals
+		residuals: residuals.into_iter().collect()
 	})
 }
 
-fn query_delete_files<I: Iterator<Item: AsRef<Path>>>(files: I) -> Result<bool, dialoguer::Error> {
+pub fn query_delete_files<I: Iterator<Item: AsRef<Path>>>(files: I) -> Result<bool, dialoguer::Error> {
 	dialoguer::Confirm::new()
 		.with_prompt(format!(
 			"{}: {}\n{}:\n{}\n{}\n",
 			style("Warning").bold().yellow(),
-			style("Output notes directory contains unrecognized notes").bold(),
+			style("Output contains unrecognized notes or images").bold(),
 			"Continuing will cause the following files to be permanently deleted, with any persistent notes being lost",
 			files.map(|s| { format!(" - {}\n", style(s.as_ref().to_string_lossy()).cyan()) }).collect::<String>(),
 			style("Do you still want to proceed?").bold().magenta(),
 		)).default(false)
 		.report(false) // dialoguer bug causes this to sometimes cause duplication.
 		.interact()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{env, fs};
+
+	use super::*;
+
+	#[test]
+	fn reject_names_taken_by_other_items() {
+		let dir = env::temp_dir().join(format!("szo-notes-{}", std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+
+		let out = dir.join("Collection/Name");
+		fs::create_dir_all(out.join(SOURCE_PREFIX)).unwrap();
+		fs::create_dir_all(out.join(ANNOTATIONS_PREFIX)).unwrap();
+
+		fs::write(out.join(SOURCE_PREFIX).join("Name.md"), "---\nzotero_key: OTHER\n---\n").unwrap();
+		fs::write(out.join(ANNOTATIONS_PREFIX).join("Taken.md"), "---\nzotero_key: OTHER\nzotero_annotation_key: X\n---\n").unwrap();
+		fs::write(out.join(ANNOTATIONS_PREFIX).join("Plain.md"), "Written by hand").unwrap();
+
+		let vault = VaultIndex::new(&dir).unwrap();
+		let fetch = |source_name: &str, annotation: &str| get_note_files(&vault, &out, None, "KEY", source_name, vec![annotation.to_owned()].into_iter(), |name| (name.clone(), name.clone()));
+
+		// * Notes without identifying properties are still adopted by name.
+		let files = fetch("Other", "Plain").unwrap();
+		assert!(!files.source.exists);
+		assert!(files.annotations.map(|(_, file)| file).all(|file| file.exists));
+
+		assert!(matches!(fetch("Name", "New"), Err(NoteFetchError::NameTaken(path)) if path == out.join(SOURCE_PREFIX).join("Name.md")));
+		assert!(matches!(fetch("Other", "Taken"), Err(NoteFetchError::NameTaken(path)) if path == out.join(ANNOTATIONS_PREFIX).join("Taken.md")));
+
+		fs::remove_dir_all(dir).unwrap();
+	}
 }
\ No newline at end of file
@@ -1,29 +1,207 @@
-use itertools::{EitherOrBoth, Itertools};
+use serde::{ser::SerializeMap, Serialize};
 
-use crate::global::{PERSISTENT_BEGIN, PERSISTENT_END};
+use crate::global::{PERSISTENT_BEGIN, PERSISTENT_BEGIN_NAMED, PERSISTENT_END};
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum FetchPersistentError {
 	NestedSections(usize),
-	MismatchedDelimiters
+	MismatchedDelimiters,
+	InvalidName(usize),
+	DuplicateName(String)
 }
 
-pub fn get_persistent_sections<'a>(data: &'a str) -> Result<Vec<&'a str>, FetchPersistentError> {
-	let starts = data.match_indices(PERSISTENT_BEGIN).map(|(index, _)| index);
-	let ends = data.match_indices(PERSISTENT_END).map(|(index, _)| index);
+/// A single persistent section within a note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PersistentSection<'a> {
+	/// The name of the section, or `None` for the default section.
+	pub name: Option<&'a str>,
+	pub content: &'a str
+}
+
+/// An opening delimiter located within a note.
+#[derive(Debug, Clone, Copy)]
+struct BeginDelimiter<'a> {
+	start: usize,
+	len: usize,
+	name: Option<&'a str>
+}
+
+/// Finds the next opening delimiter at or after `from`.
+fn find_begin(data: &str, from: usize) -> Result<Option<BeginDelimiter<'_>>, FetchPersistentError> {
+	let (prefix, suffix) = PERSISTENT_BEGIN_NAMED;
+
+	let unnamed = data[from..].find(PERSISTENT_BEGIN).map(|index| from + index);
+	let named = data[from..].find(prefix).map(|index| from + index);
+
+	let start = match (unnamed, named) {
+		(Some(unnamed), Some(named)) if named < unnamed => named,
+		(Some(unnamed), _) => return Ok(Some(BeginDelimiter { start: unnamed, len: PERSISTENT_BEGIN.len(), name: None })),
+		(None, Some(named)) => named,
+		(None, None) => return Ok(None)
+	};
+
+	let name_start = start + prefix.len();
+	let name_len = data[name_start..].find(suffix).ok_or(FetchPersistentError::InvalidName(start))?;
+	let name = &data[name_start..(name_start + name_len)];
+
+	if !is_section_name(name) { return Err(FetchPersistentError::InvalidName(start)); }
+
+	Ok(Some(BeginDelimiter { start, len: prefix.len() + name_len + suffix.len(), name: Some(name) }))
+}
+
+/// Checks if a string is usable as the name of a persistent section.
+pub fn is_section_name(name: &str) -> bool {
+	!name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+pub fn get_persistent_sections(data: &str) -> Result<Vec<PersistentSection<'_>>, FetchPersistentError> {
+	let mut out: Vec<PersistentSection> = Vec::new();
+
+	let mut position = 0;
 
-	let mut out: Vec<&'a str> = Vec::new();
+	while let Some(BeginDelimiter { start, len, name }) = find_begin(data, position)? {
+		let content_start = start + len;
 
-	let mut last_end = 0;
+		let end = data[content_start..].find(PERSISTENT_END).map(|index| content_start + index).ok_or(FetchPersistentError::MismatchedDelimiters)?;
 
-	for pair in starts.zip_longest(ends) {
-		let EitherOrBoth::Both(start, end) = pair else { return Err(FetchPersistentError::MismatchedDelimiters); };
+		if let Some(nested) = find_begin(&data[..end], content_start)? { return Err(FetchPersistentError::NestedSections(nested.start)); }
 
-		if start < last_end { return Err(FetchPersistentError::NestedSections(start)); }
-		
-		last_end = end + PERSISTENT_END.len();
-		out.push(&data[(start + PERSISTENT_BEGIN.len())..end]);
+		if out.iter().any(|section| section.name == name) {
+			return Err(FetchPersistentError::DuplicateName(name.unwrap_or_default().to_owned()));
+		}
+
+		out.push(PersistentSection { name, content: &data[content_start..end] });
+		position = end + PERSISTENT_END.len();
 	}
 
+	// * Any remaining closing delimiter has no matching opening delimiter.
+	if data[position..].contains(PERSISTENT_END) { return Err(FetchPersistentError::MismatchedDelimiters); }
+
 	Ok(out)
+}
+
+/// Writes the opening and closing delimiters of a persistent section around its content.
+pub fn format_persistent_section(name: Option<&str>, content: &str) -> String {
+	match name {
+		Some(name) => format!("{}{name}{}{content}{PERSISTENT_END}", PERSISTENT_BEGIN_NAMED.0, PERSISTENT_BEGIN_NAMED.1),
+		None => format!("{PERSISTENT_BEGIN}{content}{PERSISTENT_END}")
+	}
+}
+
+/// Persistent section content carried over from a previous version of a note, in the order it appeared.
+#[derive(Debug, Clone, Default)]
+pub struct PersistedSections {
+	sections: Vec<(Option<String>, String)>
+}
+
+impl PersistedSections {
+	pub fn parse(data: &str) -> Result<Self, FetchPersistentError> {
+		Ok(Self {
+			sections: get_persistent_sections(data)?.into_iter().map(|section| (section.name.map(|s| s.to_owned()), section.content.to_owned())).collect()
+		})
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = PersistentSection<'_>> {
+		self.sections.iter().map(|(name, content)| PersistentSection { name: name.as_deref(), content })
+	}
+
+	/// Adds a named section, unless a section with the same name is already present.
+	pub fn insert_missing<N: Into<String>, C: Into<String>>(&mut self, name: N, content: C) {
+		let name = name.into();
+
+		if self.sections.iter().any(|(existing, _)| existing.as_deref() == Some(name.as_str())) { return; }
+
+		self.sections.push((Some(name), content.into()));
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.sections.is_empty()
+	}
+
+	/// Moves the sections merged in from the note of an annotation (named `{key}` or `{key}-{name}`) back into that note, under their original names.
+	///
+	/// Sections that the note already has are left in place.
+	pub fn restore_merged(&mut self, key: &str, note: &mut PersistedSections) {
+		let prefix = format!("{key}-");
+
+		self.sections.retain(|(name, content)| {
+			let original = match name.as_deref() {
+				Some(name) if name == key => None,
+				Some(name) => match name.strip_prefix(&prefix) {
+					Some(original) => Some(original),
+					None => return true
+				},
+				None => return true
+			};
+
+			if note.sections.iter().any(|(existing, _)| existing.as_deref() == original) { return true; }
+
+			note.sections.push((original.map(|name| name.to_owned()), content.clone()));
+			false
+		});
+	}
+}
+
+/// Serialized as a map from section names to content, with the default section using an empty name.
+impl Serialize for PersistedSections {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut map = serializer.serialize_map(Some(self.sections.len()))?;
+
+		for (name, content) in &self.sections {
+			map.serialize_entry(name.as_deref().unwrap_or_default(), content)?;
+		}
+
+		map.end()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_sections() {
+		assert_eq!(get_persistent_sections("a<!--SZO-Persist-Begin-->x%%SZO-Persist-End%%b<!--SZO-Persist-Begin:summary-->y%%SZO-Persist-End%%"), Ok(vec![
+			PersistentSection { name: None, content: "x" },
+			PersistentSection { name: Some("summary"), content: "y" }
+		]));
+
+		assert_eq!(get_persistent_sections("no sections"), Ok(vec![]));
+
+		assert_eq!(get_persistent_sections("<!--SZO-Persist-Begin-->x"), Err(FetchPersistentError::MismatchedDelimiters));
+		assert_eq!(get_persistent_sections("x%%SZO-Persist-End%%"), Err(FetchPersistentError::MismatchedDelimiters));
+		assert_eq!(get_persistent_sections("<!--SZO-Persist-Begin--><!--SZO-Persist-Begin:a-->%%SZO-Persist-End%%"), Err(FetchPersistentError::NestedSections(24)));
+		assert_eq!(get_persistent_sections("<!--SZO-Persist-Begin:a b-->%%SZO-Persist-End%%"), Err(FetchPersistentError::InvalidName(0)));
+		assert_eq!(
+			get_persistent_sections("<!--SZO-Persist-Begin:a-->%%SZO-Persist-End%%<!--SZO-Persist-Begin:a-->%%SZO-Persist-End%%"),
+			Err(FetchPersistentError::DuplicateName("a".to_owned()))
+		);
+	}
+
+	#[test]
+	fn restore_merged_sections() {
+		let mut literature = PersistedSections::parse(&[
+			format_persistent_section(None, "mine"),
+			format_persistent_section(Some("KEY"), "default"),
+			format_persistent_section(Some("KEY-summary"), "summary"),
+			format_persistent_section(Some("KEY-kept"), "merged"),
+			format_persistent_section(Some("OTHER"), "other")
+		].concat()).unwrap();
+
+		let mut note = PersistedSections::parse(&format_persistent_section(Some("kept"), "existing")).unwrap();
+
+		literature.restore_merged("KEY", &mut note);
+
+		assert_eq!(literature.iter().collect::<Vec<_>>(), [
+			PersistentSection { name: None, content: "mine" },
+			PersistentSection { name: Some("KEY-kept"), content: "merged" },
+			PersistentSection { name: Some("OTHER"), content: "other" }
+		]);
+
+		assert_eq!(note.iter().collect::<Vec<_>>(), [
+			PersistentSection { name: Some("kept"), content: "existing" },
+			PersistentSection { name: None, content: "default" },
+			PersistentSection { name: Some("summary"), content: "summary" }
+		]);
+	}
 }
\ No newline at end of file
@@ -0,0 +1,178 @@
+use std::{collections::HashMap, fs::File, io::{self, BufRead, BufReader}, path::{Path, PathBuf}};
+
+use serde::Deserialize;
+
+use crate::util::directory::get_files_ext_recursive;
+
+/// The identifying frontmatter properties of a generated note.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct NoteIdentity {
+	/// The key of the Zotero item the note was generated for (the parent item, for annotation notes).
+	pub zotero_key: Option<String>,
+	/// The key of the Zotero annotation the note was generated for, if any.
+	pub zotero_annotation_key: Option<String>,
+	/// The key of the Zotero item a link stub points to, for stubs placed in a document's other collections.
+	pub zotero_stub: Option<String>
+}
+
+/// Maps Zotero keys to the notes within the vault that were generated for them.
+#[derive(Debug, Clone, Default)]
+pub struct VaultIndex {
+	identities: HashMap<PathBuf, NoteIdentity>,
+	sources: HashMap<String, PathBuf>,
+	annotations: HashMap<String, PathBuf>,
+	stubs: HashMap<String, Vec<PathBuf>>
+}
+
+impl VaultIndex {
+	/// Indexes every note within a vault by its identifying properties.
+	///
+	/// If multiple notes claim the same key, the first in path order is used.
+	pub fn new<P: AsRef<Path>>(root: P) -> Result<Self, io::Error> {
+		let mut out = Self::default();
+
+		if !root.as_ref().is_dir() { return Ok(out); }
+
+		for path in get_files_ext_recursive(root, "md")? {
+			let identity = match read_identity(&path) {
+				Ok(Some(identity)) => identity,
+				Ok(None) => continue,
+				Err(e) if e.kind() == io::ErrorKind::InvalidData => continue,
+				Err(e) => return Err(e)
+			};
+
+			out.insert(path, identity);
+		}
+
+		Ok(out)
+	}
+
+	/// Re-reads notes that were written, moved, or removed since the vault was indexed, so that later imports see them.
+	pub fn refresh<I: IntoIterator<Item = PathBuf>>(&mut self, paths: I) -> Result<(), io::Error> {
+		let paths: Vec<PathBuf> = paths.into_iter().collect();
+
+		// * Every path is forgotten first, so that a moved note is not shadowed by its old location.
+		for path in &paths { self.forget(path); }
+
+		for path in paths {
+			if !path.is_file() { continue; }
+
+			match read_identity(&path) {
+				Ok(Some(identity)) => self.insert(path, identity),
+				Ok(None) => (),
+				Err(e) if e.kind() == io::ErrorKind::InvalidData => (),
+				Err(e) => return Err(e)
+			}
+		}
+
+		Ok(())
+	}
+
+	fn insert(&mut self, path: PathBuf, identity: NoteIdentity) {
+		match (&identity.zotero_key, &identity.zotero_annotation_key, &identity.zotero_stub) {
+			(_, Some(annotation), _) => { self.annotations.entry(annotation.clone()).or_insert_with(|| path.clone()); },
+			(Some(source), None, _) => { self.sources.entry(source.clone()).or_insert_with(|| path.clone()); },
+			(None, None, Some(stub)) => self.stubs.entry(stub.clone()).or_default().push(path.clone()),
+			(None, None, None) => return
+		}
+
+		self.identities.insert(path, identity);
+	}
+
+	fn forget(&mut self, path: &Path) {
+		let Some(identity) = self.identities.remove(path) else { return; };
+
+		if let Some(key) = &identity.zotero_annotation_key {
+			if self.annotations.get(key).is_some_and(|found| found == path) { self.annotations.remove(key); }
+		} else if let Some(key) = &identity.zotero_key {
+			if self.sources.get(key).is_some_and(|found| found == path) { self.sources.remove(key); }
+		} else if let Some(key) = &identity.zotero_stub {
+			if let Some(stubs) = self.stubs.get_mut(key) { stubs.retain(|stub| stub != path); }
+		}
+	}
+
+	pub fn identity<P: AsRef<Path>>(&self, path: P) -> Option<&NoteIdentity> {
+		self.identities.get(path.as_ref())
+	}
+
+	pub fn find_source(&self, key: &str) -> Option<&Path> {
+		self.sources.get(key).map(|path| path.as_path())
+	}
+
+	pub fn find_annotation(&self, key: &str) -> Option<&Path> {
+		self.annotations.get(key).map(|path| path.as_path())
+	}
+
+	/// Returns every link stub pointing to a given source.
+	pub fn stubs_of(&self, source_key: &str) -> &[PathBuf] {
+		self.stubs.get(source_key).map(|paths| paths.as_slice()).unwrap_or_default()
+	}
+
+	/// Returns every note that was generated for a given source, including its annotation notes.
+	pub fn notes_of<'a>(&'a self, source_key: &'a str) -> impl Iterator<Item = &'a Path> {
+		self.identities.iter().filter(move |(_, identity)| identity.zotero_key.as_deref() == Some(source_key)).map(|(path, _)| path.as_path())
+	}
+}
+
+/// Reads the identifying properties from a note's frontmatter.
+///
+/// Returns `None` if the note has no frontmatter, or if it could not be parsed.
+pub fn read_identity<P: AsRef<Path>>(path: P) -> Result<Option<NoteIdentity>, io::Error> {
+	let mut lines = BufReader::new(File::open(path)?).lines();
+
+	match lines.next().transpose()? {
+		Some(line) if line.trim_end() == "---" => (),
+		_ => return Ok(None)
+	}
+
+	let mut frontmatter = String::new();
+
+	for line in lines {
+		let line = line?;
+
+		if line.trim_end() == "---" {
+			return Ok(serde_yml::from_str::<NoteIdentity>(&frontmatter).ok().filter(|identity| *identity != NoteIdentity::default()));
+		}
+
+		frontmatter.push_str(&line);
+		frontmatter.push('\n');
+	}
+
+	Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{env, fs};
+
+	use super::*;
+
+	#[test]
+	fn refresh_touched_notes() {
+		let dir = env::temp_dir().join(format!("szo-vault-{}", std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+
+		fs::write(dir.join("Old.md"), "---\nzotero_key: KEY\n---\n").unwrap();
+
+		let mut vault = VaultIndex::new(&dir).unwrap();
+
+		// * The source note is moved, and an annotation note is written.
+		fs::remove_file(dir.join("Old.md")).unwrap();
+		fs::write(dir.join("New.md"), "---\nzotero_key: KEY\n---\n").unwrap();
+		fs::write(dir.join("Annotation.md"), "---\nzotero_key: KEY\nzotero_annotation_key: A\n---\n").unwrap();
+
+		vault.refresh([dir.join("New.md"), dir.join("Old.md"), dir.join("Annotation.md")]).unwrap();
+
+		assert_eq!(vault.find_source("KEY"), Some(dir.join("New.md").as_path()));
+		assert_eq!(vault.find_annotation("A"), Some(dir.join("Annotation.md").as_path()));
+		assert!(vault.identity(dir.join("Old.md")).is_none());
+
+		fs::remove_file(dir.join("Annotation.md")).unwrap();
+		vault.refresh([dir.join("Annotation.md")]).unwrap();
+
+		assert_eq!(vault.find_annotation("A"), None);
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+}
\ No newline at end of file
@@ -1,2 +1,3 @@
+pub mod bibliography;
 pub mod import;
 pub mod select;
\ No newline at end of file
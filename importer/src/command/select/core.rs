@@ -2,7 +2,7 @@ use std::{fmt::{self, Display}, fs, path::PathBuf};
 
 use console::style;
 use dialoguer::theme::Theme;
-use crate::{api::select::index::{self, Library}, core::{CollectionFilePathError, LibraryCache, LibraryIndexFormatError}, util::versioned};
+use crate::{api::select::index::{self, Document, Library}, core::{CollectionResolver, LibraryCache, LibraryIndexFormatError, ResolveError}, util::{terminal::is_attended, versioned}};
 use crate::api::select::selection::{Selection, SelectionOutput};
 
 use crate::{global::API_VERSION, ProgramConfig, ProgramError};
@@ -12,7 +12,54 @@ pub struct SelectArgs {
 	#[arg(short, long)]
 	file: PathBuf,
 	#[arg(short, long)]
-	out: PathBuf
+	out: PathBuf,
+
+	/// Select the library by name or ID instead of prompting for it.
+	#[arg(long)]
+	library: Option<String>,
+
+	/// Select the document with the given ID.
+	#[arg(long)]
+	document_id: Option<i64>,
+
+	/// Select the document with the given Zotero item key.
+	#[arg(long)]
+	key: Option<String>,
+
+	/// Select a document whose title contains the given text (ignoring case).
+	#[arg(long)]
+	title: Option<String>,
+
+	/// Select a document within the given collection, written as a '/' separated path of collection names.
+	#[arg(long)]
+	collection: Option<String>,
+
+	/// Only select documents that are in no collection.
+	#[arg(long)]
+	unfiled: bool
+}
+
+impl SelectArgs {
+	/// Checks if any document query arguments were given, in which case the selection is made without prompting.
+	fn has_document_query(&self) -> bool {
+		self.document_id.is_some() || self.key.is_some() || self.title.is_some() || self.collection.is_some() || self.unfiled
+	}
+
+	fn matches_library(&self, library: &Library) -> bool {
+		self.library.as_ref().is_none_or(|query| library.name == *query || library.id.to_string() == *query)
+	}
+
+	fn matches_document(&self, cache: &LibraryCache, document: &Document) -> bool {
+		(!self.unfiled || document.collection_ids.is_empty())
+			&& self.document_id.is_none_or(|id| document.id == id)
+			&& self.key.as_ref().is_none_or(|key| document.key == *key)
+			&& self.title.as_ref().is_none_or(|title| document.title.to_lowercase().contains(&title.to_lowercase()))
+			&& self.collection.as_ref().is_none_or(|path| {
+				let query: Vec<&str> = path.split('/').map(|name| name.trim()).filter(|name| !name.is_empty()).collect();
+
+				document.collection_ids.iter().filter_map(|id| cache.get_collection(*id)).any(|collection| collection.get_path().names().eq(query.iter().copied()))
+			})
+	}
 }
 
 pub struct ThemeFix;
@@ -33,20 +80,30 @@ impl Theme for ThemeFix {
 
 pub enum SelectError {
 	UserExit,
-	BadIndex,
-	AmbiguousCollections(Box<[String]>),
-	DuplicateCollectionIds,
-	DangerousCollectionName
+	/// A prompt was required, but no user is present to answer it.
+	Unattended,
+	/// The index file could not be read or parsed, for the given reason.
+	BadIndex(String),
+	NoMatchingLibrary(String),
+	NoMatchingDocument,
+	AmbiguousDocuments(Box<[String]>),
+	UnknownCollection(i64),
+	/// The remembered collection choices could not be read or written.
+	CollectionChoices(String),
+	InteractError,
+	DuplicateCollectionIds
 }
 
 impl From<SelectError> for ProgramError {
 	fn from(value: SelectError) -> Self {
 		match value {
 			SelectError::UserExit => ProgramError::UserExit,
-			SelectError::BadIndex => todo!(),
-			SelectError::AmbiguousCollections(items) => todo!(),
-			SelectError::DuplicateCollectionIds => todo!(),
-			SelectError::DangerousCollectionName => todo!()
+			SelectError::Unattended => ProgramError::Unattended,
+			SelectError::BadIndex(_) | SelectError::DuplicateCollectionIds => ProgramError::BadIndexFormat,
+			SelectError::NoMatchingLibrary(_) | SelectError::NoMatchingDocument | SelectError::AmbiguousDocuments(_) => ProgramError::InvalidSelection,
+			SelectError::UnknownCollection(_) => ProgramError::BadIndexFormat,
+			SelectError::CollectionChoices(_) => ProgramError::FilesystemError,
+			SelectError::InteractError => ProgramError::InteractError
 		}
 	}
 }
@@ -59,93 +116,133 @@ impl From<LibraryIndexFormatError> for SelectError {
 	}
 }
 
-impl From<CollectionFilePathError> for SelectError {
-	fn from(value: CollectionFilePathError) -> Self {
-		match value {
-			CollectionFilePathError::DangerousSegmentName => SelectError::DangerousCollectionName,
-		}
-	}
-}
-
 impl Display for SelectError {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		match self {
 			SelectError::UserExit => Ok(()),
-			SelectError::BadIndex => todo!(),
-			SelectError::AmbiguousCollections(items) => {
-				write!(
-					f,
-					"{}: {}\n{}:\n{}\n{}: {}",
-					style("Error").bold().red(),
-					style("The selected document was found in multiple collections").bold(),
-					"The following collections contain the selection",
-					items.iter().map(|s| { format!(" - {}\n", style(s).cyan()) }).collect::<String>(),
-					style("Help").cyan(), "Remove the document from all but at most one collection, then retry"
-				)
+			SelectError::Unattended => {
+				writeln!(f, "{}: {}", style("Error").bold().red(), style("A selection prompt is required, but no user is present to answer it").bold())?;
+				write!(f, "{}: Provide a query through the '--document-id', '--key', '--title', or '--collection' arguments", style("Help").cyan())
+			},
+			SelectError::BadIndex(reason) => {
+				writeln!(f, "{}: {}", style("Error").bold().red(), style("Unable to read the index file").bold())?;
+				write!(f, "{}: {reason}", style("Reason").bold())
+			},
+			SelectError::NoMatchingLibrary(query) => {
+				write!(f, "{}: {}", style("Error").bold().red(), style(format!("No library matches '{query}'")).bold())
+			},
+			SelectError::NoMatchingDocument => {
+				write!(f, "{}: {}", style("Error").bold().red(), style("No document matches the given query").bold())
+			},
+			SelectError::AmbiguousDocuments(items) => {
+				writeln!(f, "{}: {}", style("Error").bold().red(), style("The query matched multiple documents").bold())?;
+				writeln!(f, "The following documents match the query:")?;
+
+				for item in items { writeln!(f, " - {}", style(item).cyan())?; }
+
+				write!(f, "\n{}: Narrow down the query, or select by '--document-id' or '--key'", style("Help").cyan())
 			},
-			SelectError::DuplicateCollectionIds => todo!(),
-			SelectError::DangerousCollectionName => todo!()
+			SelectError::UnknownCollection(id) => {
+				write!(f, "{}: {}", style("Error").bold().red(), style(format!("The selected document is in a collection ({id}) missing from the index")).bold())
+			},
+			SelectError::CollectionChoices(reason) => {
+				writeln!(f, "{}: {}", style("Error").bold().red(), style("Unable to access remembered collection choices").bold())?;
+				write!(f, "{}: {reason}", style("Reason").bold())
+			},
+			SelectError::InteractError => {
+				write!(f, "{}: {}", style("Error").bold().red(), style("Console interaction error").bold())
+			},
+			SelectError::DuplicateCollectionIds => {
+				write!(f, "{}: {}", style("Error").bold().red(), style("The index contains multiple collections with the same ID").bold())
+			}
 		}
 	}
 }
 
+/// Marks documents that are in no collection when listing them.
+fn unfiled_marker(document: &Document) -> &'static str {
+	if document.collection_ids.is_empty() { " [unfiled]" } else { "" }
+}
+
 pub fn select(config: &ProgramConfig, verbose: bool, args: SelectArgs) -> Result<(), SelectError> {
-	let index_file: String = fs::read_to_string(args.file).unwrap();
+	let index_file: String = fs::read_to_string(&args.file).map_err(|e| SelectError::BadIndex(format!("Unable to read '{}' ({e})", args.file.to_string_lossy())))?;
 
 	let index: index::User = versioned::deserialize_json_str_track(API_VERSION, &index_file).map_err(|e| {
-		match e {
-			versioned::Error::InvalidVersion(version) => {
-				eprintln!("{}: {}", style("Error").bold().red(), style("Unsupported API version").bold());
-				eprintln!("{}: A select query was made using version '{version}', but only '{API_VERSION}' is supported", style("Info").bold());
-			},
-			versioned::Error::Inner(e) => {
-				eprintln!("{}: {}", style("Error").bold().red(), style("Invalid API query").bold());
-				eprintln!("{}: {e}", style("Info").bold());
-			},
-		}
-		
-		SelectError::BadIndex
+		SelectError::BadIndex(match e {
+			versioned::Error::InvalidVersion(version) => format!("A select query was made using version '{version}', but only '{API_VERSION}' is supported"),
+			versioned::Error::Inner(e) => format!("Invalid API query ({e})")
+		})
 	})?;
 
-	// * Select a library automatically if only one exists, or prompt the user to choose otherwise.
-	let library = match TryInto::<&[Library; 1]>::try_into(index.libraries.as_ref()) {
-		Ok(arr) => &arr[0],
-		Err(_) => match dialoguer::FuzzySelect::with_theme(&ThemeFix {})
-			.with_prompt("Select a library:")
-			.items(&index.libraries.iter().map(|lib| &lib.name).collect::<Vec<_>>())
+	let libraries: Vec<&Library> = index.libraries.iter().filter(|lib| args.matches_library(lib)).collect();
+
+	if let (Some(query), true) = (&args.library, libraries.is_empty()) { return Err(SelectError::NoMatchingLibrary(query.clone())); }
+
+	let (library, document) = if args.has_document_query() {
+		// * Search every matching library, requiring that exactly one document matches.
+		let mut matches: Vec<(&Library, &Document)> = Vec::new();
+
+		for library in libraries {
+			let cache: LibraryCache = LibraryCache::new(library)?;
+
+			matches.extend(library.documents.iter().filter(|doc| args.matches_document(&cache, doc)).map(|doc| (library, doc)));
+		}
+
+		match matches.as_slice() {
+			[] => return Err(SelectError::NoMatchingDocument),
+			[selected] => *selected,
+			_ => return Err(SelectError::AmbiguousDocuments(
+				matches.iter().map(|(lib, doc)| format!("{} (ID {}, key {}, in {}){}", doc.title, doc.id, doc.key, lib.name, unfiled_marker(doc))).collect()
+			))
+		}
+	} else {
+		if !is_attended() { return Err(SelectError::Unattended); }
+
+		// * Select a library automatically if only one exists, or prompt the user to choose otherwise.
+		let library: &Library = match TryInto::<&[&Library; 1]>::try_into(libraries.as_slice()) {
+			Ok(arr) => arr[0],
+			Err(_) => match dialoguer::FuzzySelect::with_theme(&ThemeFix {})
+				.with_prompt("Select a library:")
+				.items(&libraries.iter().map(|lib| &lib.name).collect::<Vec<_>>())
+				.interact_opt()
+				.unwrap()
+			{
+				Some(i) => libraries[i],
+				None => return Err(SelectError::UserExit),
+			},
+		};
+
+		let document = match dialoguer::FuzzySelect::with_theme(&ThemeFix {})
+			.with_prompt("Select a document:")
+			.items(&library.documents.iter().map(|doc| format!("{}{}", doc.title, unfiled_marker(doc))).collect::<Vec<_>>())
 			.interact_opt()
-			.unwrap()
-		{
-			Some(i) => &index.libraries[i],
-			None => return Err(SelectError::UserExit),
-		},
+			.unwrap() {
+				Some(i) => &library.documents[i],
+				None => return Err(SelectError::UserExit),
+			};
+
+		(library, document)
 	};
 
 	let cache: LibraryCache = LibraryCache::new(library)?;
 
-	let document = match dialoguer::FuzzySelect::with_theme(&ThemeFix {})
-		.with_prompt("Select a document:")
-		.items(&library.documents.iter().map(|doc| &doc.title).collect::<Vec<_>>())
-		.interact_opt()
-		.unwrap() {
-			Some(i) => &library.documents[i],
-			None => return Err(SelectError::UserExit),
-		};
-
 	// println!("Importing as nested");
 	// output_path.push(TryInto::<PathBuf>::try_into(cache.get_collection(collection_id).unwrap().get_path())?);
 	// output_path.set_file_name(args.out);
 
-	if !document.collection_ids.is_empty() {
-		if document.collection_ids.len() > 1 {
-			return Err(SelectError::AmbiguousCollections(
-				document.collection_ids.iter().map(|id| {
-					cache.get_collection(*id).unwrap().get_path().to_string()
-				}).collect()
-			));
-		};
+	// * Choose the collection now if a prompt is needed, so that the import can use the remembered choice.
+	let mut resolver = CollectionResolver::load(&config.collections, &config.data_path).map_err(|error| SelectError::CollectionChoices(error.to_string()))?;
+
+	match resolver.resolve(library, &cache, document) {
+		// * Without a user present, the choice is left to the import.
+		Ok(_) | Err(ResolveError::Unattended) => (),
+		Err(ResolveError::UserExit) => return Err(SelectError::UserExit),
+		Err(ResolveError::UnknownCollection(id)) => return Err(SelectError::UnknownCollection(id)),
+		Err(ResolveError::Interact(_)) => return Err(SelectError::InteractError)
 	}
 
+	resolver.save().map_err(|error| SelectError::CollectionChoices(error.to_string()))?;
+
 	if verbose { println!("Writing output file to {}", args.out.to_string_lossy()); }
 
 	fs::write(&args.out, serde_json::to_string(&SelectionOutput {
@@ -0,0 +1,267 @@
+use std::fmt::{self, Display};
+
+use itertools::Itertools;
+use serde::Deserialize;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::api::import::source::SourceImport;
+
+/// Title words skipped by the title fields of citation keys.
+const STOP_WORDS: &[&str] = &["a", "an", "the", "on", "of", "in", "at", "to", "for", "and", "from", "with", "by", "der", "die", "das", "le", "la", "les"];
+
+/// The number of title words used by `shorttitle` when no count is given.
+const SHORT_TITLE_WORDS: usize = 3;
+
+/// A citation key formula in the style of Better BibTeX, made up of literal text and `[field]` or `[field:modifier:...]` segments.
+///
+/// Modifiers are either a count (for `authors` and `shorttitle`) or one of the `lower` and `upper` filters, as in `[auth:lower][year][shorttitle:1]`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct KeyFormula {
+	segments: Box<[KeySegment]>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum KeySegment {
+	Literal(String),
+	Field(KeyField, Option<KeyFilter>)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyField {
+	/// The first author's last name, falling back like note names do.
+	Auth,
+	/// The last names of the first `n` authors (or whoever the source is attributed to), or of every author.
+	Authors(Option<usize>),
+	/// The publication year.
+	Year,
+	/// The first `n` significant words of the title, capitalized.
+	ShortTitle(usize),
+	/// Every significant word of the title, capitalized.
+	Title,
+	/// The Zotero key of the source.
+	Key
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyFilter {
+	Lower,
+	Upper
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormulaParseError {
+	UnclosedField,
+	UnmatchedBracket,
+	UnknownField(String),
+	InvalidModifier(String)
+}
+
+impl Display for FormulaParseError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			FormulaParseError::UnclosedField => write!(f, "field is missing a closing ']'"),
+			FormulaParseError::UnmatchedBracket => write!(f, "unmatched ']'"),
+			FormulaParseError::UnknownField(name) => write!(f, "unknown field '{name}'"),
+			FormulaParseError::InvalidModifier(modifier) => write!(f, "invalid modifier '{modifier}'")
+		}
+	}
+}
+
+impl Default for KeyFormula {
+	fn default() -> Self {
+		Self::parse("[auth:lower][year][shorttitle:1]").unwrap()
+	}
+}
+
+impl KeyFormula {
+	pub fn parse(input: &str) -> Result<Self, FormulaParseError> {
+		let mut segments: Vec<KeySegment> = Vec::new();
+		let mut literal = String::new();
+		let mut chars = input.chars();
+
+		while let Some(c) = chars.next() {
+			match c {
+				'[' => {
+					let mut inner = String::new();
+
+					loop {
+						match chars.next() {
+							Some(']') => break,
+							Some(c) => inner.push(c),
+							None => return Err(FormulaParseError::UnclosedField)
+						}
+					}
+
+					if !literal.is_empty() { segments.push(KeySegment::Literal(std::mem::take(&mut literal))); }
+					segments.push(parse_field(&inner)?);
+				},
+				']' => return Err(FormulaParseError::UnmatchedBracket),
+				c => literal.push(c)
+			}
+		}
+
+		if !literal.is_empty() { segments.push(KeySegment::Literal(literal)); }
+
+		Ok(Self { segments: segments.into_boxed_slice() })
+	}
+
+	/// Builds the key of a source, before disambiguation.
+	pub fn render(&self, source: &SourceImport) -> String {
+		self.segments.iter().map(|segment| match segment {
+			KeySegment::Literal(literal) => literal.clone(),
+			KeySegment::Field(field, filter) => {
+				let value = field_value(*field, source);
+
+				match filter {
+					Some(KeyFilter::Lower) => value.to_lowercase(),
+					Some(KeyFilter::Upper) => value.to_uppercase(),
+					None => value
+				}
+			}
+		}).collect()
+	}
+}
+
+impl TryFrom<String> for KeyFormula {
+	type Error = FormulaParseError;
+
+	fn try_from(value: String) -> Result<Self, Self::Error> {
+		Self::parse(&value)
+	}
+}
+
+fn parse_field(input: &str) -> Result<KeySegment, FormulaParseError> {
+	let mut parts = input.split(':').map(str::trim);
+	let name = parts.next().unwrap_or_default();
+
+	let mut count: Option<usize> = None;
+	let mut filter: Option<KeyFilter> = None;
+
+	for modifier in parts {
+		match modifier {
+			"lower" => filter = Some(KeyFilter::Lower),
+			"upper" => filter = Some(KeyFilter::Upper),
+			_ => count = Some(modifier.parse().ok().filter(|count| *count > 0).ok_or_else(|| FormulaParseError::InvalidModifier(modifier.to_owned()))?)
+		}
+	}
+
+	let no_count = |field: KeyField| match count {
+		Some(count) => Err(FormulaParseError::InvalidModifier(count.to_string())),
+		None => Ok(field)
+	};
+
+	let field = match name {
+		"auth" => no_count(KeyField::Auth)?,
+		"authors" => KeyField::Authors(count),
+		"year" => no_count(KeyField::Year)?,
+		"shorttitle" => KeyField::ShortTitle(count.unwrap_or(SHORT_TITLE_WORDS)),
+		"title" => no_count(KeyField::Title)?,
+		"key" => no_count(KeyField::Key)?,
+		_ => return Err(FormulaParseError::UnknownField(name.to_owned()))
+	};
+
+	Ok(KeySegment::Field(field, filter))
+}
+
+fn field_value(field: KeyField, source: &SourceImport) -> String {
+	let title_words = || {
+		let title = source.metadata.title.as_deref().unwrap_or(&source.title);
+
+		title.split_whitespace().map(fold)
+			.filter(|word| !word.is_empty() && !STOP_WORDS.contains(&word.to_lowercase().as_str()))
+			.map(|word| capitalize(&word))
+			.collect::<Vec<_>>()
+	};
+
+	match field {
+		KeyField::Auth => fold(&source.attributed_name()),
+		KeyField::Authors(count) => source.attribution().1.iter().take(count.unwrap_or(usize::MAX)).map(|creator| fold(creator.name.last())).join(""),
+		KeyField::Year => source.parse_date().map(|date| date.year.to_string()).unwrap_or_else(|_| "nd".to_owned()),
+		KeyField::ShortTitle(count) => title_words().into_iter().take(count).join(""),
+		KeyField::Title => title_words().join(""),
+		KeyField::Key => source.key.clone()
+	}
+}
+
+/// Generates the key of a source through the formula, adding a letter suffix if the key is already taken.
+///
+/// Keys are compared ignoring case, as they may be used to name notes.
+pub fn generate_key<F: Fn(&str) -> bool>(source: &SourceImport, formula: &KeyFormula, is_taken: F) -> String {
+	let base = formula.render(source);
+	let mut key = base.clone();
+
+	for index in 0.. {
+		if !key.is_empty() && !is_taken(&key.to_lowercase()) { break; }
+		key = format!("{base}{}", suffix(index));
+	}
+
+	key
+}
+
+/// The citation key set for a source in Zotero, if any.
+pub fn pinned_key(source: &SourceImport) -> Option<&str> {
+	source.metadata.citation_key.as_deref().map(str::trim).filter(|key| !key.is_empty())
+}
+
+/// Reduces text to ASCII letters and digits, dropping accents from letters.
+fn fold(text: &str) -> String {
+	text.nfd().filter(char::is_ascii_alphanumeric).collect()
+}
+
+fn capitalize(word: &str) -> String {
+	let mut chars = word.chars();
+
+	match chars.next() {
+		Some(first) => first.to_uppercase().chain(chars).collect(),
+		None => String::new()
+	}
+}
+
+/// The disambiguating suffix of a key, counting `a` to `z` and then `aa`, `ab`, and so on.
+fn suffix(mut index: usize) -> String {
+	let mut out = Vec::new();
+
+	loop {
+		out.push(b'a' + (index % 26) as u8);
+		if index < 26 { break; }
+		index = index / 26 - 1;
+	}
+
+	out.iter().rev().map(|c| *c as char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::api::shared::CreatorRole;
+
+	use super::*;
+
+	fn source(key: &str, author: &str, date: &str, title: &str, pinned: Option<&str>) -> SourceImport {
+		SourceImport::fixture().with_key(key).with_title(title).with_date(date).with_person(CreatorRole::Author, "A", author).with_metadata(serde_json::json!({ "citation_key": pinned }))
+	}
+
+	#[test]
+	fn render_formula() {
+		assert_eq!(KeyFormula::default().render(&source("E", "", "undated", "", None)), "anonymousnd");
+
+		let source = source("KEY", "Müller-Lyer", "May 2020", "The deep sea: a study", None);
+
+		assert_eq!(KeyFormula::default().render(&source), "mullerlyer2020Deep");
+		assert_eq!(KeyFormula::parse("[auth]_[shorttitle:upper]-[key:lower]").unwrap().render(&source), "MullerLyer_DEEPSEASTUDY-key");
+		assert_eq!(KeyFormula::parse("[auth"), Err(FormulaParseError::UnclosedField));
+		assert_eq!(KeyFormula::parse("[author]"), Err(FormulaParseError::UnknownField("author".to_owned())));
+		assert_eq!(KeyFormula::parse("[year:2]"), Err(FormulaParseError::InvalidModifier("2".to_owned())));
+		assert_eq!(KeyFormula::parse("[shorttitle:0]"), Err(FormulaParseError::InvalidModifier("0".to_owned())));
+	}
+
+	#[test]
+	fn disambiguate_keys() {
+		let source = source("A", "Müller", "2020", "The Deep Sea", None);
+		let taken = ["muller2020deep", "muller2020deepa"];
+
+		assert_eq!(generate_key(&source, &KeyFormula::default(), |key| taken.contains(&key)), "muller2020Deepb");
+		assert_eq!(generate_key(&source, &KeyFormula::parse("[year]").unwrap(), |_| false), "2020");
+		assert_eq!((suffix(0), suffix(25), suffix(26), suffix(27)), ("a".to_owned(), "z".to_owned(), "aa".to_owned(), "ab".to_owned()));
+	}
+}
\ No newline at end of file
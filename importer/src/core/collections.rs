@@ -0,0 +1,185 @@
+use std::{io, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{api::select::index::{Document, Library}, global::COLLECTION_CHOICES_NAME, util::{path::is_contained_path, terminal::is_attended}};
+
+use super::{load_state, save_state, LibraryCache, StateLoadError};
+
+/// How the primary collection of a document filed under multiple collections is chosen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollectionPolicy {
+	/// The most deeply nested collection, with ties broken alphabetically.
+	Deepest,
+	/// The first collection in alphabetical order of its path.
+	Alphabetical,
+	/// The collection named by a document tag of the form `<tag_prefix><path>`, falling back to the deepest collection.
+	Tagged,
+	/// Ask the user, remembering the choice for later imports.
+	#[default]
+	Prompt
+}
+
+/// Controls which collection folder documents are imported to.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct CollectionConfig {
+	pub policy: CollectionPolicy,
+	/// The tag prefix read by the `tagged` policy, followed by a '/' separated collection path.
+	pub tag_prefix: String,
+	/// Whether to write link stubs to the document's other collections.
+	pub stubs: bool,
+	/// The folder (relative to the workspace) that documents in no collection are imported to; left empty to use the workspace root.
+	pub unfiled: PathBuf
+}
+
+impl CollectionConfig {
+	/// Checks that the unfiled folder stays within the workspace.
+	pub fn is_valid_unfiled(&self) -> bool {
+		is_contained_path(&self.unfiled)
+	}
+}
+
+impl Default for CollectionConfig {
+	fn default() -> Self {
+		Self { policy: CollectionPolicy::default(), tag_prefix: "collection:".to_owned(), stubs: true, unfiled: PathBuf::from("Unfiled") }
+	}
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CollectionChoices {
+	choices: Vec<CollectionChoice>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CollectionChoice {
+	library_id: i64,
+	document_id: i64,
+	collection_id: i64
+}
+
+#[derive(Debug)]
+pub enum ResolveError {
+	UserExit,
+	/// A prompt was required, but no user is present to answer it.
+	Unattended,
+	/// The document refers to a collection missing from the index.
+	UnknownCollection(i64),
+	Interact(dialoguer::Error)
+}
+
+impl From<dialoguer::Error> for ResolveError {
+	fn from(value: dialoguer::Error) -> Self {
+		Self::Interact(value)
+	}
+}
+
+/// A candidate collection, along with the names of the collections along its path.
+type Candidate = (i64, Vec<String>);
+
+/// Chooses the primary collection of documents according to the configured policy.
+#[derive(Debug)]
+pub struct CollectionResolver<'a> {
+	config: &'a CollectionConfig,
+	path: PathBuf,
+	choices: CollectionChoices,
+	modified: bool
+}
+
+impl<'a> CollectionResolver<'a> {
+	/// Loads the choices remembered from previous runs out of the data directory.
+	pub fn load(config: &'a CollectionConfig, data_path: &Path) -> Result<Self, StateLoadError> {
+		let path = data_path.join(COLLECTION_CHOICES_NAME);
+		let choices: CollectionChoices = load_state(&path)?;
+
+		Ok(Self { config, path, choices, modified: false })
+	}
+
+	/// Chooses the collection a document is imported to, returning `None` if the document is in no collection.
+	pub fn resolve(&mut self, library: &Library, cache: &LibraryCache, document: &Document) -> Result<Option<i64>, ResolveError> {
+		let candidates: Vec<Candidate> = document.collection_ids.iter().map(|id| {
+			let collection = cache.get_collection(*id).ok_or(ResolveError::UnknownCollection(*id))?;
+
+			Ok((*id, collection.get_path().names().map(|name| name.to_owned()).collect()))
+		}).collect::<Result<_, ResolveError>>()?;
+
+		if candidates.len() <= 1 { return Ok(candidates.first().map(|(id, _)| *id)); }
+
+		match self.config.policy {
+			CollectionPolicy::Prompt => self.prompt(library, document, &candidates).map(Some),
+			policy => Ok(choose(policy, &self.config.tag_prefix, &document.tags, &candidates))
+		}
+	}
+
+	fn prompt(&mut self, library: &Library, document: &Document, candidates: &[Candidate]) -> Result<i64, ResolveError> {
+		let remembered = self.choices.choices.iter().find(|choice| choice.library_id == library.id && choice.document_id == document.id);
+
+		// * A remembered choice is only used while the document is still in that collection.
+		if let Some(choice) = remembered.filter(|choice| candidates.iter().any(|(id, _)| *id == choice.collection_id)) {
+			return Ok(choice.collection_id);
+		}
+
+		if !is_attended() { return Err(ResolveError::Unattended); }
+
+		let selection = dialoguer::Select::new()
+			.with_prompt(format!("'{}' is in multiple collections; select the one to import it into", document.title))
+			.items(&candidates.iter().map(|(_, names)| names.join(" > ")).collect::<Vec<_>>())
+			.default(0)
+			.interact_opt()?
+			.ok_or(ResolveError::UserExit)?;
+
+		let collection_id = candidates[selection].0;
+
+		self.choices.choices.retain(|choice| choice.library_id != library.id || choice.document_id != document.id);
+		self.choices.choices.push(CollectionChoice { library_id: library.id, document_id: document.id, collection_id });
+		self.modified = true;
+
+		Ok(collection_id)
+	}
+
+	/// Writes any newly made choices back to the data directory.
+	pub fn save(&self) -> Result<(), io::Error> {
+		if !self.modified { return Ok(()); }
+
+		save_state(&self.path, &self.choices)
+	}
+}
+
+/// Applies a non-interactive policy to a set of (at least one) candidate collections.
+fn choose(policy: CollectionPolicy, tag_prefix: &str, tags: &[String], candidates: &[Candidate]) -> Option<i64> {
+	let alphabetical = |a: &&Candidate, b: &&Candidate| a.1.iter().map(|name| name.to_lowercase()).cmp(b.1.iter().map(|name| name.to_lowercase()));
+	let deepest = || candidates.iter().min_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| alphabetical(a, b)));
+
+	let chosen = match policy {
+		CollectionPolicy::Deepest | CollectionPolicy::Prompt => deepest(),
+		CollectionPolicy::Alphabetical => candidates.iter().min_by(alphabetical),
+		CollectionPolicy::Tagged => tags.iter().filter_map(|tag| tag.strip_prefix(tag_prefix)).find_map(|path| {
+			candidates.iter().find(|(_, names)| names.iter().map(|name| name.as_str()).eq(path.split('/').map(|name| name.trim()).filter(|name| !name.is_empty())))
+		}).or_else(deepest)
+	};
+
+	chosen.map(|(id, _)| *id)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn choose_collection() {
+		let candidates: Vec<Candidate> = vec![
+			(1, vec!["b".to_owned()]),
+			(2, vec!["a".to_owned(), "x".to_owned()]),
+			(3, vec!["A".to_owned()]),
+			(4, vec!["c".to_owned(), "y".to_owned()])
+		];
+
+		assert_eq!(choose(CollectionPolicy::Deepest, "", &[], &candidates), Some(2));
+		assert_eq!(choose(CollectionPolicy::Alphabetical, "", &[], &candidates), Some(3));
+		assert_eq!(choose(CollectionPolicy::Tagged, "collection:", &["collection:c/y".to_owned()], &candidates), Some(4));
+		assert_eq!(choose(CollectionPolicy::Tagged, "collection:", &["other".to_owned()], &candidates), Some(2));
+	}
+}
\ No newline at end of file
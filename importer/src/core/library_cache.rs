@@ -1,8 +1,8 @@
-use std::{collections::BTreeMap, fmt::Display, path::{Path, PathBuf}};
+use std::{collections::BTreeMap, fmt::Display, path::PathBuf};
 
 use itertools::Itertools;
 
-use crate::util::path::is_path_segment;
+use crate::util::path::sanitize_path_segment;
 use crate::api::select::index::{Collection, Library};
 
 #[derive(Debug, Clone)]
@@ -37,33 +37,25 @@ impl<'a> CollectionPath<'a> {
 	pub fn push(&mut self, value: i64) {
 		self.path.push(value);
 	}
-}
 
-pub enum CollectionFilePathError {
-	DangerousSegmentName
+	/// Returns the names of the collections along the path, outermost first.
+	pub fn names(&self) -> impl Iterator<Item = &str> {
+		self.path.iter().map(|id| { self.cache.get_collection_raw(*id).unwrap().collection.name.as_str() })
+	}
 }
 
-impl<'a> TryFrom<CollectionPath<'a>> for PathBuf {
-	type Error = CollectionFilePathError;
-
-	fn try_from(value: CollectionPath<'a>) -> Result<Self, Self::Error> {
-		let mut out = PathBuf::new();
-
-		for segment in value.path {
-			let name = &value.cache.get_collection_raw(segment).unwrap().collection.name;
-
-			if !is_path_segment(name) { return Err(CollectionFilePathError::DangerousSegmentName) }
-			
-			out.push(Path::new(name));
-		}
-
-		Ok(out)
+/// Converts each collection into its folder name (see `LibraryCache::folder_name`).
+/// 
+/// As sanitization is lossy, the original path should be recorded wherever it needs to be recovered.
+impl<'a> From<CollectionPath<'a>> for PathBuf {
+	fn from(value: CollectionPath<'a>) -> Self {
+		value.path.iter().map(|id| value.cache.folder_name(*id).unwrap()).collect()
 	}
 }
 
 impl<'a> Display for CollectionPath<'a> {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		write!(f, "{}", self.path.iter().map(|id| { &self.cache.get_collection_raw(*id).unwrap().collection.name }).join(" > "))
+		write!(f, "{}", self.names().join(" > "))
 	}
 }
 
@@ -131,4 +123,48 @@ impl<'a> LibraryCache<'a> {
 	pub fn get_collection(&self, id: i64) -> Option<BoundCollectionCacheEntry> {
 		self.get_collection_raw(id).map(|entry| unsafe { BoundCollectionCacheEntry::new(self, entry) })
 	}
+
+	/// Returns the sanitized name of a collection's folder.
+	///
+	/// Sibling collections whose names sanitize alike (ignoring case) would share a folder, so all but the oldest of them (by id) have their id appended.
+	pub fn folder_name(&self, id: i64) -> Option<String> {
+		let entry = self.get_collection_raw(id)?;
+		let name = sanitize_path_segment(&entry.collection.name);
+
+		let shadowed = self.collection_map.values().any(|other| {
+			other.parent == entry.parent && other.id < id && sanitize_path_segment(&other.collection.name).to_lowercase() == name.to_lowercase()
+		});
+
+		Some(match shadowed {
+			true => format!("{name} {id}"),
+			false => name
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn disambiguate_sibling_folders() {
+		let library: Library = serde_json::from_value(serde_json::json!({
+			"id": 1, "name": "My Library", "documents": [],
+			"collections": [
+				{ "id": 3, "name": "A:B", "document_ids": [], "collections": [] },
+				{ "id": 2, "name": "A/B", "document_ids": [], "collections": [
+					{ "id": 5, "name": "a b", "document_ids": [], "collections": [] }
+				] },
+				{ "id": 4, "name": "a b", "document_ids": [], "collections": [] }
+			]
+		})).unwrap();
+
+		let Ok(cache) = LibraryCache::new(&library) else { panic!("Invalid library") };
+		let folder = |id: i64| PathBuf::from(cache.get_collection(id).unwrap().get_path());
+
+		assert_eq!(folder(2), PathBuf::from("A B"));
+		assert_eq!(folder(3), PathBuf::from("A B 3"));
+		assert_eq!(folder(4), PathBuf::from("a b 4"));
+		assert_eq!(folder(5), PathBuf::from("A B/a b"));
+	}
 }
\ No newline at end of file
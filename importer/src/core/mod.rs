@@ -1,2 +1,14 @@
 mod library_cache;
-pub use library_cache::*;
\ No newline at end of file
+pub use library_cache::*;
+
+mod collections;
+pub use collections::*;
+
+mod state;
+pub use state::*;
+
+mod sources;
+pub use sources::*;
+
+mod citekey;
+pub use citekey::*;
\ No newline at end of file
@@ -0,0 +1,116 @@
+use std::{io, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{api::import::source::SourceImport, global::SOURCES_NAME};
+
+use super::{generate_key, load_state, pinned_key, save_state, KeyFormula, StateLoadError};
+
+/// An imported source, along with the citation key it was assigned.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecordedSource {
+	pub citation_key: String,
+	pub source: SourceImport
+}
+
+/// The citation key chosen for a source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyAssignment {
+	pub key: String,
+	/// The title of another source already cited by the key, which can only happen for keys pinned in Zotero.
+	pub conflict: Option<String>
+}
+
+/// Every source imported into the vault, kept in the order they were first imported.
+#[derive(Debug, Clone)]
+pub struct SourceRegistry {
+	path: PathBuf,
+	sources: Vec<RecordedSource>,
+	modified: bool
+}
+
+impl SourceRegistry {
+	pub fn load(data_path: &Path) -> Result<Self, StateLoadError> {
+		let path = data_path.join(SOURCES_NAME);
+		let sources: Vec<RecordedSource> = load_state(&path)?;
+
+		Ok(Self { path, sources, modified: false })
+	}
+
+	pub fn sources(&self) -> &[RecordedSource] {
+		&self.sources
+	}
+
+	fn find(&self, source: &SourceImport) -> Option<&RecordedSource> {
+		self.sources.iter().find(|existing| existing.source.library == source.library && existing.source.key == source.key)
+	}
+
+	/// Determines the citation key of a source.
+	///
+	/// A key pinned in Zotero is always used. Otherwise, a source keeps the key it was first assigned, and new sources are given a key
+	/// not yet used by any other source; so keys already assigned never change as sources are added.
+	pub fn citation_key(&self, source: &SourceImport, formula: &KeyFormula) -> KeyAssignment {
+		let others = || self.sources.iter().filter(|existing| existing.source.library != source.library || existing.source.key != source.key);
+
+		if let Some(key) = pinned_key(source) {
+			let conflict = others().find(|other| other.citation_key.eq_ignore_ascii_case(key)).map(|other| other.source.title.clone());
+			return KeyAssignment { key: key.to_owned(), conflict };
+		}
+
+		let key = match self.find(source) {
+			Some(existing) => existing.citation_key.clone(),
+			None => generate_key(source, formula, |key| others().any(|other| other.citation_key.to_lowercase() == key))
+		};
+
+		KeyAssignment { key, conflict: None }
+	}
+
+	/// Records an imported source along with its citation key, replacing its previous record without changing its position.
+	pub fn record(&mut self, source: &SourceImport, citation_key: &str) {
+		let record = RecordedSource { citation_key: citation_key.to_owned(), source: source.clone() };
+
+		match self.sources.iter_mut().find(|existing| existing.source.library == source.library && existing.source.key == source.key) {
+			Some(existing) => *existing = record,
+			None => self.sources.push(record)
+		}
+
+		self.modified = true;
+	}
+
+	/// Writes the record back to the data directory, if any sources were recorded.
+	pub fn save(&self) -> Result<(), io::Error> {
+		if !self.modified { return Ok(()); }
+
+		save_state(&self.path, &self.sources)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::api::shared::CreatorRole;
+
+	use super::*;
+
+	fn source(key: &str, pinned: Option<&str>) -> SourceImport {
+		SourceImport::fixture().with_key(key).with_title(key).with_person(CreatorRole::Author, "A", "Müller").with_metadata(serde_json::json!({ "citation_key": pinned }))
+	}
+
+	#[test]
+	fn keep_assigned_keys() {
+		let mut registry = SourceRegistry { path: PathBuf::new(), sources: Vec::new(), modified: false };
+		let formula = KeyFormula::parse("[auth:lower][year]").unwrap();
+
+		let mut import = |source: SourceImport| {
+			let assignment = registry.citation_key(&source, &formula);
+			registry.record(&source, &assignment.key);
+			assignment
+		};
+
+		assert_eq!(import(source("A", None)).key, "muller2020");
+		assert_eq!(import(source("B", None)).key, "muller2020a");
+		assert_eq!(import(source("C", Some("muller2020a"))), KeyAssignment { key: "muller2020a".to_owned(), conflict: Some("B".to_owned()) });
+		assert_eq!(import(source("B", None)).key, "muller2020a");
+		assert_eq!(import(source("D", None)).key, "muller2020b");
+	}
+}
\ No newline at end of file
@@ -0,0 +1,41 @@
+use std::{fmt::{self, Display}, fs, io, path::Path};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{global::STATE_VERSION, util::versioned};
+
+#[derive(Serialize)]
+struct StateFile<'a, T> {
+	version: i64,
+	data: &'a T
+}
+
+#[derive(Debug)]
+pub enum StateLoadError {
+	Io(io::Error),
+	Format(versioned::Error<serde_json::Error>)
+}
+
+impl Display for StateLoadError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			StateLoadError::Io(error) => write!(f, "{error}"),
+			StateLoadError::Format(versioned::Error::InvalidVersion(version)) => write!(f, "unsupported version '{version}', only '{STATE_VERSION}' is supported"),
+			StateLoadError::Format(versioned::Error::Inner(error)) => write!(f, "{error}")
+		}
+	}
+}
+
+/// Reads a file the importer keeps in its data directory, which starts out empty if it does not exist yet.
+pub fn load_state<T: DeserializeOwned + Default>(path: &Path) -> Result<T, StateLoadError> {
+	match fs::read_to_string(path) {
+		Ok(data) => versioned::deserialize_json_str_track(STATE_VERSION, &data).map_err(StateLoadError::Format),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+		Err(e) => Err(StateLoadError::Io(e))
+	}
+}
+
+/// Writes a file to the importer's data directory.
+pub fn save_state<T: Serialize>(path: &Path, data: &T) -> Result<(), io::Error> {
+	fs::write(path, serde_json::to_string_pretty(&StateFile { version: STATE_VERSION, data })?)
+}
\ No newline at end of file
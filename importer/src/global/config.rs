@@ -1,10 +1,17 @@
 pub const CONFIG_VERSION: i64 = 1;
-pub const API_VERSION: i64 = 1;
+pub const API_VERSION: i64 = 6;
+/// Version of the files the importer keeps in its data directory.
+pub const STATE_VERSION: i64 = 1;
 
 pub const SOURCE_PREFIX: &str = "";
 pub const ANNOTATIONS_PREFIX: &str = "annotations";
 
 pub const LOG_NAME: &str = "import.log";
+pub const COLLECTION_CHOICES_NAME: &str = "collections.json";
+/// Records every imported source, which the bibliography is built from.
+pub const SOURCES_NAME: &str = "sources.json";
 
 pub const PERSISTENT_BEGIN: &str = "<!--SZO-Persist-Begin-->";
+/// Opening delimiter of a named persistent section, written around the section name.
+pub const PERSISTENT_BEGIN_NAMED: (&str, &str) = ("<!--SZO-Persist-Begin:", "-->");
 pub const PERSISTENT_END: &str = "%%SZO-Persist-End%%";
\ No newline at end of file
@@ -3,7 +3,8 @@
 use std::{env, fs::{self}, path::PathBuf, process::{ExitCode, Termination}};
 
 use clap::Parser as _;
-use command::{import::ImportArgs, select::SelectArgs};
+use command::{bibliography::BibliographyArgs, import::{AnnotationConfig, CitationConfig, ImportArgs, NamingConfig, OutputConfig, PropertiesConfig, TemplateConfig, TextConfig}, select::SelectArgs};
+use crate::core::CollectionConfig;
 use global::CONFIG_VERSION;
 use console::style;
 use global::init;
@@ -36,14 +37,20 @@ enum ProgramError {
 	InaccessibleConfig,
 	InvalidConfig,
 	InvalidAPIQuery,
+	InvalidSelection,
 	UnsupportedConfigVersion,
 	UnsupportedAPIVersion,
-	BadImportFormat,
 	BadIndexFormat,
+	InvalidTemplate,
+	TemplateError,
+	NamingError,
 	AnnotationParseError,
+	MalformedNote,
 	FilesystemError,
 	InteractError,
-	YAMLDeserializeError
+	YAMLDeserializeError,
+	/// Some documents of a batch import failed.
+	IncompleteBatch
 }
 
 impl From<dialoguer::Error> for ProgramError {
@@ -65,7 +72,23 @@ struct ProgramConfig {
 	#[serde(default)]
 	log_coloring: bool,
 	data_path: PathBuf,
-	workspace_path: PathBuf
+	workspace_path: PathBuf,
+	#[serde(default)]
+	templates: TemplateConfig,
+	#[serde(default)]
+	naming: NamingConfig,
+	#[serde(default)]
+	collections: CollectionConfig,
+	#[serde(default)]
+	annotations: AnnotationConfig,
+	#[serde(default)]
+	output: OutputConfig,
+	#[serde(default)]
+	text: TextConfig,
+	#[serde(default)]
+	properties: PropertiesConfig,
+	#[serde(default)]
+	citations: CitationConfig
 }
 
 struct ProgramResult {
@@ -103,7 +126,9 @@ struct Cli {
 #[derive(clap::Subcommand, Debug)]
 enum Command {
 	Select(SelectArgs),
-	Import(ImportArgs)
+	Import(ImportArgs),
+	/// Write a BibTeX and CSL-JSON bibliography of the imported sources.
+	Bibliography(BibliographyArgs)
 }
 
 fn main() -> ProgramResult {
@@ -113,9 +138,6 @@ fn main() -> ProgramResult {
 fn run() -> Result<(), ProgramError> {
 	unsafe { env::set_var("RUST_BACKTRACE", "1") };
 
-	// ! TODO: This is checking the wrong thing; need to look at stdin, not any of the outputs.
-	if !console::user_attended_stderr() { return Err(ProgramError::Unattended); } // TODO: Just auto-fail prompts if unattended
-
 	// * Parse cli arguments.
 	let cli = Cli::parse();
 
@@ -178,6 +200,9 @@ fn run() -> Result<(), ProgramError> {
 		Command::Import(import_args) => {
 			command::import::import(&config, verbose, import_args)?;
 		},
+		Command::Bibliography(bibliography_args) => {
+			command::bibliography::bibliography(&config, verbose, bibliography_args)?;
+		},
 	}
 
 	// ! file.sync_data()
@@ -14,5 +14,32 @@ pub fn get_files_ext<P: AsRef<Path>>(path: P, extension: &str) -> Result<Vec<Pat
 		}
 	}
 
+	Ok(out)
+}
+
+/// Returns a list of paths to all files within a directory tree matching an extension.
+/// 
+/// Hidden directories (those whose names begin with a `.`) are skipped.
+pub fn get_files_ext_recursive<P: AsRef<Path>>(path: P, extension: &str) -> Result<Vec<PathBuf>, io::Error> {
+	let mut out: Vec<PathBuf> = Vec::new();
+	let mut pending: Vec<PathBuf> = vec![path.as_ref().to_owned()];
+
+	while let Some(dir) = pending.pop() {
+		for entry in fs::read_dir(dir)? {
+			let entry = entry?;
+
+			let path = entry.path();
+			let file_type = entry.file_type()?;
+
+			if file_type.is_dir() {
+				if !entry.file_name().to_string_lossy().starts_with('.') { pending.push(path); }
+			} else if file_type.is_file() && path.extension().map(|ext| { ext == extension }).unwrap_or(false) {
+				out.push(path);
+			}
+		}
+	}
+
+	out.sort();
+
 	Ok(out)
 }
\ No newline at end of file
@@ -73,6 +73,11 @@ impl HumanDate {
 }
 
 impl Month {
+	/// The month's number, counting January as 1.
+	pub fn number(self) -> u32 {
+		self as u32 + 1
+	}
+
 	fn get_map() -> &'static HashMap<&'static str, MonthParse> {
 		static STRING_MAP: LazyLock<HashMap<&str, MonthParse>> = LazyLock::new(|| HashMap::from([
 			("January",   MonthParse::new(Month::January,   false)), ("Jan",  MonthParse::new(Month::January,   true)),
@@ -5,4 +5,6 @@ pub mod path;
 pub mod text;
 pub mod versioned;
 pub mod human_date;
-pub mod error;
\ No newline at end of file
+pub mod error;
+pub mod transaction;
+pub mod terminal;
\ No newline at end of file
@@ -1,10 +1,88 @@
-/// Checks if a string corresponds to a single valid path segment.
-/// 
-/// Is extremely restrictive to be safe, only allowing basic ASCII alphanumeric characters.
-pub fn is_path_segment<T: AsRef<str>>(segment: T) -> bool {
-	for character in segment.as_ref().chars() {
-		if !character.is_ascii_alphanumeric() { return false; }
+use std::path::{Component, Path};
+
+use unicode_normalization::UnicodeNormalization;
+
+/// Characters that are either illegal in file names on some platform, or that break Obsidian links.
+const UNSAFE_FILE_NAME_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|', '[', ']', '#', '^'];
+
+/// Device names reserved by Windows, which cannot be used as a file stem regardless of extension.
+const RESERVED_NAMES: &[&str] = &[
+	"CON", "PRN", "AUX", "NUL",
+	"COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+	"LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9"
+];
+
+/// The maximum length of a sanitized path segment in bytes.
+///
+/// Most filesystems allow 255 bytes, so this leaves room for extensions and the keys or ids appended to disambiguate names.
+pub const MAX_SEGMENT_LEN: usize = 200;
+
+/// Converts an arbitrary string into a single path segment that is valid on all major platforms.
+///
+/// The name is normalized to NFC, unsafe characters are replaced with spaces, whitespace runs are collapsed, and leading or trailing whitespace and dots are removed.
+/// Reserved names have an underscore appended, and overly long names are truncated at a character boundary.
+pub fn sanitize_path_segment<T: AsRef<str>>(name: T) -> String {
+	let replaced: String = name.as_ref().nfc().map(|c| {
+		if c.is_control() || UNSAFE_FILE_NAME_CHARS.contains(&c) { ' ' } else { c }
+	}).collect();
+
+	let collapsed = replaced.split_whitespace().collect::<Vec<_>>().join(" ");
+	let trim = |s: &str| s.trim_matches(|c: char| c == '.' || c.is_whitespace()).to_owned();
+
+	let mut out = trim(&collapsed);
+
+	if out.len() > MAX_SEGMENT_LEN {
+		let end = (0..=MAX_SEGMENT_LEN).rev().find(|i| out.is_char_boundary(*i)).unwrap_or_default();
+		out = trim(&out[..end]);
+	}
+
+	if out.is_empty() { return "Untitled".to_owned(); }
+
+	let stem = out.split('.').next().unwrap_or_default().trim_end();
+	if RESERVED_NAMES.iter().any(|reserved| stem.eq_ignore_ascii_case(reserved)) { out.push('_'); }
+
+	out
+}
+
+/// Appends a space and a suffix to a sanitized path segment, truncating the segment rather than the suffix to stay within `MAX_SEGMENT_LEN`.
+///
+/// The suffix (such as a Zotero key) must itself be safe to use in a file name.
+pub fn append_to_segment(segment: &str, suffix: &str) -> String {
+	let max = MAX_SEGMENT_LEN.saturating_sub(suffix.len() + 1);
+	let end = (0..=max.min(segment.len())).rev().find(|i| segment.is_char_boundary(*i)).unwrap_or_default();
+
+	let segment = segment[..end].trim_end_matches(|c: char| c == '.' || c.is_whitespace());
+
+	match segment.is_empty() {
+		true => suffix.to_owned(),
+		false => format!("{segment} {suffix}")
 	}
+}
 
-	true
+/// Checks that a path is relative and cannot escape the directory it is joined onto.
+pub fn is_contained_path(path: &Path) -> bool {
+	path.components().all(|component| matches!(component, Component::Normal(_)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sanitize_segments() {
+		assert_eq!(sanitize_path_segment("Machine Learning"), "Machine Learning");
+		assert_eq!(sanitize_path_segment("E\u{301}conomie"), "\u{c9}conomie");
+		assert_eq!(sanitize_path_segment("  a/b: c?  "), "a b c");
+		assert_eq!(sanitize_path_segment("notes..."), "notes");
+		assert_eq!(sanitize_path_segment("con"), "con_");
+		assert_eq!(sanitize_path_segment("LPT1.txt"), "LPT1.txt_");
+		assert_eq!(sanitize_path_segment("CONSOLE"), "CONSOLE");
+		assert_eq!(sanitize_path_segment("???"), "Untitled");
+
+		let long = sanitize_path_segment("\u{e9}".repeat(150));
+		assert!(long.len() <= MAX_SEGMENT_LEN && long.chars().all(|c| c == '\u{e9}'));
+
+		assert_eq!(append_to_segment("Name", "KEY"), "Name KEY");
+		assert_eq!(append_to_segment(&long, "KEY"), format!("{} KEY", "\u{e9}".repeat(98)));
+	}
 }
\ No newline at end of file
@@ -0,0 +1,8 @@
+use std::io::{self, IsTerminal};
+
+/// Checks if a user is present to answer interactive prompts.
+/// 
+/// Prompts are drawn to stderr and read from stdin, so both must be attached to a terminal.
+pub fn is_attended() -> bool {
+	io::stdin().is_terminal() && console::user_attended_stderr()
+}
\ No newline at end of file
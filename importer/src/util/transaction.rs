@@ -0,0 +1,315 @@
+use std::{fs, io::{self, Write}, mem, path::{Path, PathBuf}};
+
+const STAGING_SUFFIX: &str = ".szo-tmp";
+
+#[derive(Debug, Clone)]
+enum Change {
+	Write { path: PathBuf, content: Box<[u8]> },
+	Remove { path: PathBuf }
+}
+
+impl Change {
+	fn path(&self) -> &Path {
+		match self {
+			Change::Write { path, .. } | Change::Remove { path } => path
+		}
+	}
+}
+
+/// A set of filesystem changes that are applied on an all-or-nothing basis.
+///
+/// New file contents are first staged next to their targets, then moved into place through renames.
+/// If any step fails (or panics), all changes made so far are reverted.
+#[derive(Debug, Clone, Default)]
+pub struct Transaction {
+	changes: Vec<Change>,
+	/// The directory within which directories emptied by removals are removed once committed.
+	prune_root: Option<PathBuf>
+}
+
+#[derive(Debug)]
+pub struct TransactionError {
+	/// The path being operated on when the failure occurred.
+	pub path: PathBuf,
+	pub error: io::Error,
+	/// Paths that could not be restored to their original state.
+	pub unrestored: Vec<PathBuf>
+}
+
+/// Undo information for a single applied change.
+#[derive(Debug)]
+struct Applied {
+	path: PathBuf,
+	/// The original contents, or `None` if the file did not previously exist.
+	backup: Option<Vec<u8>>
+}
+
+/// Reverts all recorded changes when dropped, unless disarmed.
+#[derive(Debug, Default)]
+struct Rollback {
+	staged: Vec<PathBuf>,
+	created_dirs: Vec<PathBuf>,
+	applied: Vec<Applied>,
+	unrestored: Vec<PathBuf>
+}
+
+impl Rollback {
+	fn run(&mut self) {
+		for Applied { path, backup } in mem::take(&mut self.applied).into_iter().rev() {
+			let result = match &backup {
+				Some(content) => write_atomic(&path, content),
+				None => fs::remove_file(&path)
+			};
+
+			if result.is_err() { self.unrestored.push(path); }
+		}
+
+		for path in mem::take(&mut self.staged) { let _ = fs::remove_file(path); }
+
+		// * Directories are only removed if still empty, and in reverse creation order so that children go first.
+		for dir in mem::take(&mut self.created_dirs).into_iter().rev() { let _ = fs::remove_dir(dir); }
+	}
+
+	fn disarm(&mut self) {
+		self.staged.clear();
+		self.created_dirs.clear();
+		self.applied.clear();
+	}
+}
+
+impl Drop for Rollback {
+	fn drop(&mut self) {
+		self.run();
+	}
+}
+
+fn staging_path(path: &Path) -> PathBuf {
+	let mut name = path.file_name().unwrap_or_default().to_owned();
+	name.push(STAGING_SUFFIX);
+
+	path.with_file_name(name)
+}
+
+fn write_synced(path: &Path, content: &[u8]) -> Result<(), io::Error> {
+	let mut file = fs::File::create(path)?;
+	file.write_all(content)?;
+	file.sync_all()
+}
+
+/// Replaces the contents of a file through a rename, so that it is never left partially written.
+fn write_atomic(path: &Path, content: &[u8]) -> Result<(), io::Error> {
+	let staged = staging_path(path);
+
+	write_synced(&staged, content)?;
+	fs::rename(&staged, path).inspect_err(|_| { let _ = fs::remove_file(&staged); })
+}
+
+/// Creates a directory and any missing parents, returning the directories that were created (outermost first).
+fn create_dirs(path: &Path) -> Result<Vec<PathBuf>, io::Error> {
+	let missing: Vec<PathBuf> = path.ancestors().take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists()).map(|dir| dir.to_owned()).collect();
+
+	fs::create_dir_all(path)?;
+
+	Ok(missing.into_iter().rev().collect())
+}
+
+/// Removes the parent directories of removed files that are now empty, up to the root.
+///
+/// This happens after the changes are committed, so failures are ignored rather than reverting them.
+fn prune_dirs(root: &Path, removed: &[PathBuf]) {
+	for path in removed {
+		// * Removing a directory fails once one is not empty, leaving it and its parents in place.
+		for dir in path.ancestors().skip(1).take_while(|dir| dir.starts_with(root) && *dir != root) {
+			if fs::remove_dir(dir).is_err() { break; }
+		}
+	}
+}
+
+impl Transaction {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Replaces (or creates) a file with the given contents.
+	pub fn write<P: Into<PathBuf>, C: Into<Box<[u8]>>>(&mut self, path: P, content: C) {
+		self.changes.push(Change::Write { path: path.into(), content: content.into() });
+	}
+
+	/// Removes a file.
+	pub fn remove<P: Into<PathBuf>>(&mut self, path: P) {
+		self.changes.push(Change::Remove { path: path.into() });
+	}
+
+	/// Removes the directories left empty by removals once the transaction is committed, stopping at (and never removing) `root`.
+	pub fn prune_empty_dirs<P: Into<PathBuf>>(&mut self, root: P) {
+		self.prune_root = Some(root.into());
+	}
+
+	pub fn commit(self) -> Result<(), TransactionError> {
+		let mut rollback = Rollback::default();
+
+		let removed: Vec<PathBuf> = self.changes.iter().filter(|change| matches!(change, Change::Remove { .. })).map(|change| change.path().to_owned()).collect();
+		let result = Self::apply(self.changes, &mut rollback);
+
+		if result.is_ok() {
+			rollback.disarm();
+			if let Some(root) = self.prune_root { prune_dirs(&root, &removed); }
+
+			return Ok(());
+		}
+
+		rollback.run();
+
+		result.map_err(|(path, error)| TransactionError { path, error, unrestored: mem::take(&mut rollback.unrestored) })
+	}
+
+	fn apply(changes: Vec<Change>, rollback: &mut Rollback) -> Result<(), (PathBuf, io::Error)> {
+		// * Stage all new contents.
+		for change in &changes {
+			let Change::Write { path, content } = change else { continue; };
+			let fail = |error| (path.clone(), error);
+
+			if let Some(parent) = path.parent() { rollback.created_dirs.extend(create_dirs(parent).map_err(fail)?); }
+
+			let staged = staging_path(path);
+			rollback.staged.push(staged.clone());
+
+			write_synced(&staged, content).map_err(fail)?;
+		}
+
+		// * Move everything into place, backing up the original contents first.
+		for change in changes {
+			let path = change.path().to_owned();
+			let fail = |error| (path.clone(), error);
+
+			let backup = match fs::read(&path) {
+				Ok(content) => Some(content),
+				Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+				Err(error) => return Err(fail(error))
+			};
+
+			match change {
+				Change::Write { .. } => {
+					let staged = staging_path(&path);
+
+					fs::rename(&staged, &path).map_err(fail)?;
+					rollback.staged.retain(|p| *p != staged);
+				},
+				Change::Remove { .. } => {
+					if backup.is_none() { continue; }
+
+					fs::remove_file(&path).map_err(fail)?;
+				}
+			}
+
+			rollback.applied.push(Applied { path, backup });
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::env;
+
+	use super::*;
+
+	/// Creates an empty directory for a test to work in.
+	fn temp_dir(name: &str) -> PathBuf {
+		let dir = env::temp_dir().join(format!("szo-transaction-{}-{name}", std::process::id()));
+
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+
+		dir
+	}
+
+	fn entries(dir: &Path) -> Vec<String> {
+		let mut names: Vec<String> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+		names.sort();
+		names
+	}
+
+	#[test]
+	fn revert_failed_staging() {
+		let dir = temp_dir("staging");
+		fs::write(dir.join("a.md"), "old").unwrap();
+		fs::write(dir.join("blocker"), "").unwrap();
+
+		// * A file stands where a parent directory is needed, so staging the second write fails.
+		let mut transaction = Transaction::new();
+		transaction.write(dir.join("a.md"), b"new".to_vec());
+		transaction.write(dir.join("blocker/b.md"), b"new".to_vec());
+
+		let error = transaction.commit().unwrap_err();
+
+		assert_eq!(error.path, dir.join("blocker/b.md"));
+		assert_eq!(fs::read_to_string(dir.join("a.md")).unwrap(), "old");
+		assert_eq!(entries(&dir), ["a.md", "blocker"]);
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn revert_applied_changes() {
+		let dir = temp_dir("applied");
+		fs::write(dir.join("a.md"), "old").unwrap();
+		fs::write(dir.join("b.md"), "old").unwrap();
+		fs::create_dir(dir.join("folder")).unwrap();
+
+		// * Removing a directory fails only after the earlier changes were moved into place.
+		let mut transaction = Transaction::new();
+		transaction.write(dir.join("a.md"), b"new".to_vec());
+		transaction.remove(dir.join("b.md"));
+		transaction.write(dir.join("c.md"), b"new".to_vec());
+		transaction.remove(dir.join("folder"));
+
+		let error = transaction.commit().unwrap_err();
+
+		assert_eq!((error.path, error.unrestored), (dir.join("folder"), vec![]));
+		assert_eq!(fs::read_to_string(dir.join("a.md")).unwrap(), "old");
+		assert_eq!(fs::read_to_string(dir.join("b.md")).unwrap(), "old");
+		assert_eq!(entries(&dir), ["a.md", "b.md", "folder"]);
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn remove_created_dirs() {
+		let dir = temp_dir("dirs");
+		fs::create_dir(dir.join("folder")).unwrap();
+
+		let mut transaction = Transaction::new();
+		transaction.write(dir.join("new/nested/a.md"), b"new".to_vec());
+		transaction.remove(dir.join("folder"));
+
+		assert!(transaction.commit().is_err());
+		assert_eq!(entries(&dir), ["folder"]);
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn prune_emptied_dirs() {
+		let dir = temp_dir("prune");
+		fs::create_dir_all(dir.join("Unfiled/Document/annotations")).unwrap();
+		fs::create_dir_all(dir.join("Unfiled/Other")).unwrap();
+		fs::write(dir.join("Unfiled/Document/Document.md"), "").unwrap();
+		fs::write(dir.join("Unfiled/Document/annotations/a.md"), "").unwrap();
+		fs::write(dir.join("Unfiled/Other/Other.md"), "").unwrap();
+
+		let mut transaction = Transaction::new();
+		transaction.remove(dir.join("Unfiled/Document/Document.md"));
+		transaction.remove(dir.join("Unfiled/Document/annotations/a.md"));
+		transaction.write(dir.join("Filed/Document.md"), b"new".to_vec());
+		transaction.prune_empty_dirs(&dir);
+
+		transaction.commit().unwrap();
+
+		assert_eq!(entries(&dir), ["Filed", "Unfiled"]);
+		assert_eq!(entries(&dir.join("Unfiled")), ["Other"]);
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+}
\ No newline at end of file
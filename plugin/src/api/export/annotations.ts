@@ -2,70 +2,105 @@ import { Util } from "../../util";
 
 export class Annotation {
 	key: string;
-	kind: keyof typeof AnnotationType;
-	page: number;
+	kind: AnnotationType;
+	position: Position;
 	text?: string;
 	comment?: string;
 	colour: string;
 	date_added: string;
 	date_modified: string;
 	tags: Tag[];
+	image?: AnnotationImage;
 
 	constructor(
 		key: string,
-		kind: keyof typeof AnnotationType,
-		page: number,
+		kind: AnnotationType,
+		position: Position,
 		text: string | null,
 		comment: string | null,
 		colour: string,
 		date_added: string,
 		date_modified: string,
-		tags: Tag[]
+		tags: Tag[],
+		image: AnnotationImage | null
 	) {
 		this.key = key;
 		this.kind = kind;
-		this.page = page;
+		this.position = position;
 		if (text !== null) { this.text = text; };
 		if (comment !== null) { this.comment = comment; };
 		this.colour = colour;
 		this.date_added = date_added;
 		this.date_modified = date_modified;
 		this.tags = tags;
+		if (image !== null) { this.image = image; };
 	}
 
 	static try_from(item: Zotero.Item): Annotation | null {
 		if (!item.isAnnotation()) { return null; }
 
 		type TypeMap = {
-			[key in _ZoteroTypes.Annotations.AnnotationType]: keyof typeof AnnotationType;
+			[key in _ZoteroTypes.Annotations.AnnotationType]: AnnotationType;
 		};
 
 		const type_map: TypeMap = {
-			highlight: "Highlight",
-			image: "Unknown",
-			ink: "Unknown",
-			note: "Unknown",
-			underline: "Unknown",
-			text: "Unknown"
+			highlight: "highlight",
+			image: "image",
+			ink: "ink",
+			note: "note",
+			underline: "underline",
+			text: "text"
 		};
 
+		// * Zotero renders image annotations into a cache, which may not have been populated yet.
+		let image: AnnotationImage | null = null;
+
+		if (item.annotationType === "image") {
+			const path: string = Zotero.Annotations.getCacheImagePath(item);
+			if (Zotero.File.pathToFile(path).exists()) { image = { path }; }
+		}
+
 		return new Annotation(
 			item.key,
 			type_map[item.annotationType],
-			Number.parseInt(item.annotationPageLabel), // ! TODO: Parse position into a JSON object instead.
+			Position.from(item),
 			item.annotationText,
 			item.annotationComment,
 			item.annotationColor,
 			Zotero.Date.sqlToISO8601(item.dateAdded),
 			Zotero.Date.sqlToISO8601(item.dateModified),
-			item.getTags().map(Tag.from)
+			item.getTags().map(Tag.from),
+			image
 		);
 	}
 }
 
-export enum AnnotationType {
-	Highlight,
-	Unknown
+export type AnnotationType = "highlight" | "underline" | "note" | "text" | "image" | "ink";
+
+// * The image of an image annotation, as either a path to a PNG file or base64 encoded PNG data.
+export type AnnotationImage = { path: string } | { data: string };
+
+export class Position {
+	page_index: number;
+	page_label: string;
+	rects: number[][];
+
+	constructor(page_index: number, page_label: string, rects: number[][]) {
+		this.page_index = page_index;
+		this.page_label = page_label;
+		this.rects = rects;
+	}
+
+	static from(item: Zotero.Item): Position {
+		const position: { pageIndex: number; rects?: number[][] } = JSON.parse(item.annotationPosition);
+
+		// * Ink annotations are made up of paths rather than rectangles, and some PDFs have no page labels.
+		return new Position(
+			position.pageIndex,
+			item.annotationPageLabel || String(position.pageIndex + 1),
+			position.rects ?? []
+		);
+	}
 }
 
 export class Tag {
@@ -0,0 +1,39 @@
+import { Util } from "../../util";
+import { Annotation } from "./annotations";
+
+export class Attachment {
+	key: string;
+	title: string;
+	path: string;
+	annotations: Annotation[];
+
+	constructor(
+		key: string,
+		title: string,
+		path: string,
+		annotations: Annotation[]
+	) {
+		this.key = key;
+		this.title = title;
+		this.path = path;
+		this.annotations = annotations;
+	}
+
+	static try_from(item: Zotero.Item): Attachment | null {
+		if (!item.isPDFAttachment()) { return null; }
+
+		let path = item.getFilePath();
+		if (path == false) { return null; }
+
+		let annotations: Annotation[] = item.getAnnotations()
+			.map(Annotation.try_from)
+			.map(Util.require_defined);
+
+		return new Attachment(
+			item.key,
+			item.getDisplayTitle(),
+			path,
+			annotations
+		);
+	}
+}
\ No newline at end of file
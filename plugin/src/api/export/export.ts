@@ -1,5 +1,5 @@
-import { Util } from "../../util";
-import { Annotation } from "./annotations";
+import { Attachment } from "./attachment";
+import { Note } from "./note";
 import { Source } from "./source";
 
 export class ExportFile {
@@ -14,30 +14,36 @@ export class ExportFile {
 
 export class ZExport {
 	source: Source;
-	annotations: Annotation[];
+	attachments: Attachment[];
+	notes: Note[];
 
 	constructor(
 		source: Source,
-		annotations: Annotation[]
+		attachments: Attachment[],
+		notes: Note[]
 	) {
 		this.source = source;
-		this.annotations = annotations;
+		this.attachments = attachments;
+		this.notes = notes;
 	}
 
 	static try_from(item: Zotero.Item): ZExport | null {
-		// * Might be too restrictive?
-		if (!item.isPDFAttachment()) { return null; }
-
 		let source: Source | null = Source.try_from(item);
 		if (source == null) { return null; }
 
-		let annotations: Annotation[] = item.getAnnotations()
-			.map(Annotation.try_from)
-			.map(Util.require_defined);
+		// * Non-PDF attachments (snapshots, linked URLs, etc.) cannot hold annotations, and are left out.
+		let attachments: Attachment[] = Zotero.Items.get(item.getAttachments())
+			.map(Attachment.try_from)
+			.filter((attachment): attachment is Attachment => attachment !== null);
+
+		if (attachments.length === 0) { return null; }
+
+		let notes: Note[] = Zotero.Items.get(item.getNotes()).map(Note.from);
 
 		return new ZExport(
 			source,
-			annotations
+			attachments,
+			notes
 		);
 	}
 }
\ No newline at end of file
@@ -0,0 +1,31 @@
+export class Note {
+	key: string;
+	title: string;
+	content: string;
+	date_added: string;
+	date_modified: string;
+
+	constructor(
+		key: string,
+		title: string,
+		content: string,
+		date_added: string,
+		date_modified: string
+	) {
+		this.key = key;
+		this.title = title;
+		this.content = content;
+		this.date_added = date_added;
+		this.date_modified = date_modified;
+	}
+
+	static from(item: Zotero.Item): Note {
+		return new Note(
+			item.key,
+			item.getNoteTitle(),
+			item.getNote(),
+			Zotero.Date.sqlToISO8601(item.dateAdded),
+			Zotero.Date.sqlToISO8601(item.dateModified)
+		);
+	}
+}
\ No newline at end of file
@@ -1,4 +1,4 @@
-import { AuthorIndex } from "..";
+import { AuthorIndex, AuthorName } from "..";
 import { Util } from "../../util";
 import { Tag } from "./annotations";
 
@@ -11,11 +11,11 @@ export class Source {
 	note?: string;
 	date: string;
 	url?: string;
-	authors: AuthorIndex[];
+	creators: Creator[];
+	metadata: Metadata;
 	tags: Tag[];
 	date_added: string;
 	date_modified: string;
-	path: string;
 
 	constructor(
 		library: number,
@@ -26,11 +26,11 @@ export class Source {
 		note: string | null,
 		date: string,
 		url: string | null,
-		authors: AuthorIndex[],
+		creators: Creator[],
+		metadata: Metadata,
 		tags: Tag[],
 		date_added: string,
-		date_modified: string,
-		path: string
+		date_modified: string
 	) {
 		this.library = library;
 		this.id = id;
@@ -40,21 +40,15 @@ export class Source {
 		if (note !== null) { this.note = note; };
 		this.date = date;
 		if (url !== null) { this.url = url; };
-		this.authors = authors;
+		this.creators = creators;
+		this.metadata = metadata;
 		this.tags = tags;
 		this.date_added = date_added;
 		this.date_modified = date_modified;
-		this.path = path;
 	}
 
-	static try_from(item: Zotero.Item): Source | null {
-		if (!item.isPDFAttachment()) { return null; }
-
-		let path = item.getFilePath();
-		if (path == false) { return null; }
-
-		let parent = item.parentItem;
-		if (parent == undefined) { return null; }
+	static try_from(parent: Zotero.Item): Source | null {
+		if (!parent.isRegularItem()) { return null; }
 
 		let title: string = parent.getField("shortTitle");
 		if (title === "") { title = parent.getDisplayTitle(); }
@@ -70,11 +64,83 @@ export class Source {
 			parent.getField("abstractNote"),
 			parent.getField("date"),
 			parent.getField("url"),
-			parent.getCreatorsJSON().filter((c) => c.creatorType === "author").map(AuthorIndex.from),
+			parent.getCreatorsJSON().map(Creator.from),
+			Metadata.from(parent),
 			parent.getTags().map(Tag.from),
 			Zotero.Date.sqlToISO8601(parent.dateAdded),
-			Zotero.Date.sqlToISO8601(parent.dateModified),
-			path
+			Zotero.Date.sqlToISO8601(parent.dateModified)
 		);
 	}
+}
+
+export class Creator {
+	role: string;
+	name: AuthorName;
+
+	constructor(role: string, name: AuthorName) {
+		this.role = role;
+		this.name = name;
+	}
+
+	static from(creator: _ZoteroTypes.Item.CreatorJSON): Creator {
+		return new Creator(creator.creatorType, AuthorIndex.from(creator).name);
+	}
+}
+
+export class Metadata {
+	title?: string;
+	short_title?: string;
+	publication?: string;
+	volume?: string;
+	issue?: string;
+	pages?: string;
+	edition?: string;
+	series?: string;
+	publisher?: string;
+	place?: string;
+	doi?: string;
+	isbn?: string;
+	issn?: string;
+	language?: string;
+	citation_key?: string;
+
+	static from(item: Zotero.Item): Metadata {
+		// * Base fields map onto the equivalent field of each item type (eg. 'publicationTitle' onto 'bookTitle' for book sections).
+		let field = (name: string): string | undefined => {
+			let value: string = String(item.getField(name, false, true)).trim();
+			return value === "" ? undefined : value;
+		};
+
+		let metadata = new Metadata();
+
+		metadata.title = field("title");
+		metadata.short_title = field("shortTitle");
+		metadata.publication = field("publicationTitle");
+		metadata.volume = field("volume");
+		metadata.issue = field("issue");
+		metadata.pages = field("pages");
+		metadata.edition = field("edition");
+		metadata.series = field("series");
+		metadata.publisher = field("publisher");
+		metadata.place = field("place");
+		metadata.doi = field("DOI");
+		metadata.isbn = field("ISBN");
+		metadata.issn = field("ISSN");
+		metadata.language = field("language");
+		metadata.citation_key = Metadata.citation_key(item);
+
+		return metadata;
+	}
+
+	// Reads a citation key from Zotero's own field, or otherwise one pinned by Better BibTeX as a 'Citation Key:' line of the extra field.
+	static citation_key(item: Zotero.Item): string | undefined {
+		let native: string = String(item.getField("citationKey", false, true)).trim();
+		if (native !== "") { return native; }
+
+		let line = String(item.getField("extra")).split("\n").find((line) => /^citation key:/i.test(line.trim()));
+		if (line === undefined) { return undefined; }
+
+		let key = line.trim().slice("citation key:".length).trim();
+		return key === "" ? undefined : key;
+	}
 }
\ No newline at end of file
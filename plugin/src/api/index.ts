@@ -47,25 +47,31 @@ export class LibraryIndex extends IndexBase {
 }
 
 export class DocumentIndex extends IndexBase {
+	key: string;
 	title: string;
 	authors: AuthorIndex[];
 	collection_ids: number[];
+	tags: string[];
 	date_added: string;
 	date_modified: string;
 
 	constructor(
 		id: number,
+		key: string,
 		title: string,
 		authors: AuthorIndex[],
 		collection_ids: number[],
+		tags: string[],
 		date_added: string,
 		date_modified: string
 	) {
 		super(id);
 
+		this.key = key;
 		this.title = title;
 		this.authors = authors;
 		this.collection_ids = collection_ids;
+		this.tags = tags;
 		this.date_added = date_added;
 		this.date_modified = date_modified;
 	}
@@ -76,17 +82,19 @@ export class DocumentIndex extends IndexBase {
 
 		return new DocumentIndex(
 			item.id,
+			item.key,
 			title,
 			// Use the JSON variant since the underlying function is currently difficult to properly typecheck.
 			item.getCreatorsJSON().filter((c) => c.creatorType === "author").map(AuthorIndex.from),
 			item.getCollections(),
+			item.getTags().map((tag) => tag.tag),
 			item.dateAdded,
 			item.dateModified
 		);
 	}
 }
 
-type AuthorName = { format: string, value: string | { first: string, last: string } };
+export type AuthorName = { format: string, value: string | { first: string, last: string } };
 
 export class AuthorIndex {
 	name: AuthorName;
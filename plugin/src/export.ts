@@ -7,7 +7,7 @@ export const index_name: string = "index.json";
 export const export_name: string = "export.json";
 export const selection_name: string = "select.json";
 
-const api_version: number = 1;
+const api_version: number = 6;
 
 export async function perform_export(): Promise<true | Error> {
 	Zotero.log("Beginning ZO export");
@@ -66,17 +66,15 @@ export async function perform_export(): Promise<true | Error> {
 
 	if (!allowed_types.includes(document.itemType)) { throw new Error("Bad doc type"); }
 
-	// !
-	let attachment = document.getAttachments()[0] as number;
-	let z_export = ZExport.try_from(Zotero.Items.get(attachment));
+	let z_export = ZExport.try_from(document);
 
-	if (z_export === null) { return new Error("Failed to parse attachment"); }
+	if (z_export === null) { return new Error("Failed to parse attachments"); }
 
 	let export_file = PathUtils.join((await Util.get_data_dir()).path, export_name);
 
 	await Zotero.File.putContentsAsync(export_file, JSON.stringify(
 		new ExportFile(
-			1,
+			api_version,
 			z_export
 		),
 		null,